@@ -0,0 +1,294 @@
+//! Daemon configuration file.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::{
+    notifier::NotificationBackend,
+    state::{DeviceKind, ThresholdConfig},
+};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Config is a contents of the daemon's config file.
+///
+/// Unset values keep their built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listener: ListenerOptions,
+    pub notification: NotificationOptions,
+
+    /// Volume changes to notify about.
+    pub threshold: Option<ThresholdConfig>,
+
+    /// Remember volume of each node and restore it when node appears again.
+    pub restore_volumes: Option<bool>,
+
+    /// Max time in seconds to wait for notification daemon to show or update a notification.
+    ///
+    /// Defaults to 2 seconds. Used only by DBus backend.
+    pub notification_update_timeout_secs: Option<u64>,
+
+    /// Write daemon's PID to `$XDG_RUNTIME_DIR/reactord.pid` and refuse to start
+    /// if another instance is running.
+    ///
+    /// Instances started with `--config` use a PID file named after the config path.
+    pub create_pid_file: Option<bool>,
+}
+
+/// ListenerOptions are PipeWire listener settings, see [crate::pwloop::ListenerConfig].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerOptions {
+    /// Glob patterns of entry names or labels to ignore.
+    pub ignore_list: Option<Vec<String>>,
+
+    /// Names or labels of entries to track, all other entries are ignored.
+    ///
+    /// Can't be used together with `ignore_list`.
+    pub allow_list: Option<HashSet<String>>,
+
+    /// Custom display names by entry name or label.
+    pub aliases: Option<HashMap<String, String>>,
+
+    /// Device kinds to track, e.g. `["Sink"]`. All kinds are tracked if not set.
+    ///
+    /// Monitor sources are matched by `Monitor` kind, separately from `Source`.
+    pub watch_kinds: Option<Vec<DeviceKind>>,
+    pub suppress_wireplumber_nodes: Option<bool>,
+    pub max_message_age_ms: Option<u64>,
+    pub message_buffer_size: Option<usize>,
+    pub subscribe_initial_params: Option<bool>,
+    pub initial_sync_timeout_ms: Option<u64>,
+}
+
+/// NotificationOptions are desktop notification settings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationOptions {
+    /// Notification backend. Backend selected at compile time is used if not set.
+    pub backend: Option<NotificationBackend>,
+
+    /// Time to show notification for.
+    pub timeout_ms: Option<u64>,
+
+    /// Notification timeouts by entry name or label.
+    pub device_timeouts_ms: HashMap<String, u64>,
+    pub icon_style: Option<IconStyle>,
+
+    /// Volume levels to switch volume icon at.
+    pub icon_thresholds: Option<IconThresholds>,
+
+    /// Show per-channel volumes in notification body.
+    pub show_channel_detail: Option<bool>,
+
+    /// Keep volume notifications out of notification history.
+    pub transient: Option<bool>,
+    pub close_on_remove: Option<bool>,
+    pub show_remove: Option<bool>,
+    pub show_codec_change: Option<bool>,
+
+    /// Show notification when sample rate of an entry changes.
+    pub notify_format_change: Option<bool>,
+
+    /// Max number of notifications kept open.
+    pub max_open: Option<usize>,
+
+    /// Min interval between volume notifications of an entry.
+    pub debounce_ms: Option<u64>,
+
+    /// Min interval between identical volume notifications of an entry.
+    pub cooldown_ms: Option<u64>,
+}
+
+/// IconStyle is a style of notification icons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconStyle {
+    /// Monochrome icons, e.g. `audio-volume-high-symbolic`.
+    #[default]
+    Symbolic,
+
+    /// Full color icons, e.g. `audio-volume-high`.
+    Regular,
+}
+
+impl IconStyle {
+    /// Returns icon name in this style.
+    pub fn apply(&self, icon: &str) -> String {
+        match self {
+            Self::Symbolic => icon.to_string(),
+            Self::Regular => icon.strip_suffix("-symbolic").unwrap_or(icon).to_string(),
+        }
+    }
+}
+
+/// IconThresholds are volumes in percent above which each volume level icon is shown.
+///
+/// Volume at or below `low` is shown with muted icon.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconThresholds {
+    pub low: f32,
+    pub medium: f32,
+    pub high: f32,
+}
+
+impl Default for IconThresholds {
+    fn default() -> Self {
+        Self {
+            low: 0.0,
+            medium: 33.0,
+            high: 66.0,
+        }
+    }
+}
+
+impl IconThresholds {
+    /// Returns symbolic icon name for a volume in percent.
+    pub fn icon_for(&self, volume: f32) -> &'static str {
+        if volume > self.high {
+            "audio-volume-high-symbolic"
+        } else if volume > self.medium {
+            "audio-volume-medium-symbolic"
+        } else if volume > self.low {
+            "audio-volume-low-symbolic"
+        } else {
+            "audio-volume-muted-symbolic"
+        }
+    }
+}
+
+impl Config {
+    /// Returns default config location - `$XDG_CONFIG_HOME/reactord/config.toml`.
+    ///
+    /// Falls back to `~/.config` if `XDG_CONFIG_HOME` is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+        Some(
+            config_dir
+                .join(env!("CARGO_PKG_NAME"))
+                .join(CONFIG_FILE_NAME),
+        )
+    }
+
+    /// Loads config from a file.
+    ///
+    /// Missing file is treated as an empty config.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(data) => Self::parse(path, &data),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Loads config from a file which must exist.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(path, &data)
+    }
+
+    fn parse(path: &Path, data: &str) -> Result<Self> {
+        let config: Self =
+            toml::from_str(data).with_context(|| format!("failed to parse {}", path.display()))?;
+        if config.listener.ignore_list.is_some() && config.listener.allow_list.is_some() {
+            bail!(
+                "invalid config {}: listener.ignore_list and listener.allow_list can't be used together",
+                path.display()
+            );
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_for_switches_above_thresholds() {
+        let t = IconThresholds::default();
+        assert_eq!(t.icon_for(0.0), "audio-volume-muted-symbolic");
+        assert_eq!(t.icon_for(1.0), "audio-volume-low-symbolic");
+        assert_eq!(t.icon_for(33.0), "audio-volume-low-symbolic");
+        assert_eq!(t.icon_for(34.0), "audio-volume-medium-symbolic");
+        assert_eq!(t.icon_for(66.0), "audio-volume-medium-symbolic");
+        assert_eq!(t.icon_for(67.0), "audio-volume-high-symbolic");
+        assert_eq!(t.icon_for(150.0), "audio-volume-high-symbolic");
+    }
+
+    #[test]
+    fn parse_readme_example() {
+        let data = r#"
+            restore_volumes = true
+            notification_update_timeout_secs = 5
+
+            [listener]
+            ignore_list = ["easyeffects_*"]
+            watch_kinds = ["Sink"]
+
+            [notification]
+            timeout_ms = 1500
+            icon_thresholds = { low = 0, medium = 40, high = 80 }
+        "#;
+
+        let config = Config::parse(Path::new("config.toml"), data).unwrap();
+        assert_eq!(config.restore_volumes, Some(true));
+        assert_eq!(config.notification_update_timeout_secs, Some(5));
+        assert_eq!(config.listener.watch_kinds, Some(vec![DeviceKind::Sink]));
+        assert_eq!(config.notification.timeout_ms, Some(1500));
+        assert_eq!(
+            config.notification.icon_thresholds.map(|t| t.high),
+            Some(80.0)
+        );
+        assert!(config.create_pid_file.is_none());
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys() {
+        assert!(Config::parse(Path::new("config.toml"), "unknown = 1").is_err());
+    }
+
+    #[test]
+    fn missing_file_is_an_error_only_if_specified() {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-missing.toml",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+
+        assert!(Config::load(&path).is_ok());
+        assert!(Config::load_file(&path).is_err());
+    }
+
+    #[test]
+    fn ignore_list_and_allow_list_are_exclusive() {
+        let data = r#"
+            [listener]
+            ignore_list = ["easyeffects_sink"]
+            allow_list = ["alsa_output.pci-0000_00_1f.3.analog-stereo"]
+        "#;
+
+        let err = Config::parse(Path::new("config.toml"), data).unwrap_err();
+        assert!(err.to_string().contains("can't be used together"));
+
+        let data = r#"
+            [listener]
+            allow_list = ["alsa_output.pci-0000_00_1f.3.analog-stereo"]
+        "#;
+        assert!(Config::parse(Path::new("config.toml"), data).is_ok());
+    }
+}
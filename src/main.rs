@@ -1,213 +1,1535 @@
+mod config;
+mod notifier;
+mod pidfile;
 mod pwloop;
+mod sdnotify;
 mod state;
 mod utils;
+mod volumestore;
 
-use anyhow::{Context, Result};
-use notify_rust::{Hint, Notification, NotificationHandle};
-use state::{ActionType, Entry, State, VolumeInfo};
-use tokio::sync::oneshot;
+use std::{
+    collections::HashMap,
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use notifier::{Message, Notifier, NotifyResult};
+use state::{
+    ActionType, DefaultKind, DeviceKind, Entry, EventBus, LinkState, SpaAudioFormat, State,
+    ThresholdConfig, VOLUME_CHANGE_THRESHOLD, VolumeInfo,
+};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::oneshot,
+};
 use tracing::{debug, error, info, info_span, warn};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
-fn init_logger() {
+/// Initializes logger with a level used if `RUST_LOG` is not set.
+fn init_logger(default_level: &str) {
     let env_filter = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new(format!("{}=warn", env!("CARGO_PKG_NAME"))))
+        .or_else(|_| EnvFilter::try_new(format!("{}={default_level}", env!("CARGO_PKG_NAME"))))
         .expect("failed to get log level from environment");
 
     tracing_subscriber::registry()
         .with(env_filter)
-        .with(tracing_subscriber::fmt::layer().with_target(true))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(true)
+                .with_writer(std::io::stderr),
+        )
         .init();
 }
 
+/// OutputFormat is a format of values printed to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+
+    /// Waybar custom module payload.
+    Waybar,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "waybar" => Ok(Self::Waybar),
+            _ => Err(anyhow!("unsupported output format: {s}")),
+        }
+    }
+}
+
+const USAGE: &str = "\
+Usage: reactord [OPTIONS]
+       reactord list-devices [--json]
+
+Commands:
+  list-devices       Print audio objects known to PipeWire and exit.
+                     Use --json to print newline-delimited JSON
+
+Options:
+  --once             Print the first volume change and exit
+  --format <FORMAT>  Output format of printed values: text, json or waybar [default: text]
+  -n, --dry-run      Log events and notifications without displaying notifications
+  -c, --config <PATH>
+                     Config file path [default: $XDG_CONFIG_HOME/reactord/config.toml]
+  -h, --help         Print help
+";
+
+/// Args is a list of command line arguments.
+#[derive(Debug, Default)]
+struct Args {
+    /// Print the first volume change and exit.
+    once: bool,
+    format: OutputFormat,
+
+    /// Log events and notifications instead of displaying notifications.
+    dry_run: bool,
+
+    /// Print entries after initial sync with PipeWire and exit.
+    list_devices: bool,
+
+    /// Print entries list as JSON.
+    json: bool,
+
+    /// Config file to use instead of the default one.
+    config: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut it = std::env::args().skip(1);
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--once" => args.once = true,
+                "-n" | "--dry-run" => args.dry_run = true,
+                "list-devices" => args.list_devices = true,
+                "--json" => args.json = true,
+                "-h" | "--help" => {
+                    print!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "--format" => {
+                    args.format = it.next().context("--format requires a value")?.parse()?;
+                }
+                "-c" | "--config" => {
+                    args.config = Some(it.next().context("--config requires a value")?.into());
+                }
+                _ => {
+                    if let Some(v) = arg.strip_prefix("--format=") {
+                        args.format = v.parse()?;
+                    } else if let Some(v) = arg.strip_prefix("--config=") {
+                        args.config = Some(v.into());
+                    } else {
+                        bail!("unknown argument: {arg}");
+                    }
+                }
+            }
+        }
+
+        if args.json && !args.list_devices {
+            bail!("--json is only supported by list-devices");
+        }
+
+        Ok(args)
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    init_logger();
-    if let Err(err) = run().await {
+    let args = Args::parse();
+
+    // Dry run is useless without logs.
+    let is_dry_run = args.as_ref().is_ok_and(|args| args.dry_run);
+    init_logger(if is_dry_run { "info" } else { "warn" });
+    let args = match args {
+        Ok(args) => args,
+        Err(err) => {
+            error!("Error: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    // Unlike the default config, explicitly specified one must exist.
+    let config = match (&args.config, config::Config::default_path()) {
+        (Some(path), _) => config::Config::load_file(path),
+        (None, Some(path)) => config::Config::load(&path),
+        (None, None) => Ok(config::Config::default()),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Error: {err:#}");
+            std::process::exit(2);
+        }
+    };
+
+    let ctx = match RunContext::from_config(Arc::new(config)) {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            error!("Error: {err:#}");
+            std::process::exit(2);
+        }
+    };
+
+    // Waybar expects continuous output unless a single value is requested.
+    let watch = !args.once && args.format == OutputFormat::Waybar;
+    let list_format = if args.json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Text
+    };
+    let mut ctx = RunContext {
+        once: args.once.then_some(args.format),
+        watch: watch.then_some(args.format),
+        dry_run: args.dry_run,
+        list_devices: args.list_devices.then_some(list_format),
+        config_path: args.config.clone(),
+        ..ctx
+    };
+    if args.dry_run || args.list_devices {
+        ctx.notifier = Arc::new(notifier::DryRunNotifier);
+    }
+
+    if args.list_devices {
+        // Show all entries to help with writing an ignore list.
+        ctx.listener_config
+            .set_ignore_list(Vec::new())
+            .expect("empty ignore list is valid");
+        ctx.listener_config.set_allow_list(None);
+        ctx.listener_config.set_device_kind_filter(None);
+        ctx.listener_config.set_reconnect(false);
+    }
+
+    if let Err(err) = run(ctx).await {
         error!("Error: {err}");
+        std::process::exit(1);
     }
 }
 
-fn build_volume_notification(entry: &Entry, vol: &VolumeInfo) -> Option<Notification> {
-    let val = vol.volume.or_else(|| {
-        if vol.channel_volumes.is_empty() {
-            None
-        } else {
-            Some(vol.channel_volumes[0])
+fn build_volume_notification(
+    entry: &Entry,
+    vol: &VolumeInfo,
+    cfg: &ActionConfig,
+) -> Option<Message> {
+    let is_muted = vol.mute.unwrap_or(false);
+    let val = vol.effective_volume();
+    if !is_muted && val.is_none() {
+        error!(
+            is_muted = vol.mute,
+            ?val,
+            entry_id = entry.id,
+            "can't send notification as no mute or volume info"
+        );
+        return None;
+    }
+
+    let (summary, icon) = entry.display_name_with_icon(vol, &cfg.icon_thresholds);
+
+    // Single summary line is enough for mono entries without soft volume.
+    let body = vol
+        .format_display()
+        .filter(|_| cfg.show_channel_detail)
+        .filter(|_| vol.channel_volumes.len() >= 2 || vol.has_soft_volume());
+
+    Some(Message {
+        summary,
+        body,
+        icon: cfg.icon_style.apply(&icon),
+        value: val.filter(|_| !is_muted).map(|v| v.round() as i32),
+        timeout: cfg.notification_timeout_for(entry),
+        transient: cfg.transient_notifications,
+    })
+}
+
+/// How often to look for stale entries.
+const STALE_DEVICES_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Number of consecutive notification failures to warn about.
+const NOTIFICATION_FAILURE_STREAK_WARN: u32 = 3;
+
+fn print_volume_change(entry: &Entry, format: OutputFormat, thresholds: &config::IconThresholds) {
+    let vol = entry.volume.clone().unwrap_or_default();
+    match format {
+        OutputFormat::Text => println!("{}", entry.display_name_with_icon(&vol, thresholds).0),
+        OutputFormat::Json => {
+            let out = serde_json::json!({
+                "id": entry.id,
+                "name": entry.name,
+                "label": entry.format_label(),
+                "volume_pct": vol.effective_volume().map(|v| v.round() as i32),
+                "muted": vol.mute.unwrap_or(false),
+            });
+            println!("{out}");
         }
+        OutputFormat::Waybar => println!("{}", waybar_output(entry, &vol, thresholds)),
+    }
+}
+
+/// Returns Waybar custom module payload.
+///
+/// `class` is one of `muted`, `low`, `medium` or `high` and is intended for CSS styling.
+/// Levels are switched at the same thresholds as notification icons.
+fn waybar_output(
+    entry: &Entry,
+    vol: &VolumeInfo,
+    thresholds: &config::IconThresholds,
+) -> serde_json::Value {
+    let pct = vol.effective_volume().map(|v| v.round() as i32);
+    let (text, class) = match pct {
+        _ if vol.mute.unwrap_or(false) => ("🔇 muted".to_string(), "muted"),
+        Some(pct) if pct as f32 > thresholds.high => (format!("🔊 {pct}%"), "high"),
+        Some(pct) if pct as f32 > thresholds.medium => (format!("🔊 {pct}%"), "medium"),
+        Some(pct) => (format!("🔊 {pct}%"), "low"),
+        None => ("🔊".to_string(), "low"),
+    };
+
+    serde_json::json!({
+        "text": text,
+        "tooltip": entry.format_label(),
+        "class": class,
+        "percentage": pct,
+    })
+}
+
+/// Returns short name of a sample format, e.g. `S16LE`.
+fn format_sample_format(f: SpaAudioFormat) -> String {
+    let name = format!("{f:?}");
+    name.trim_start_matches("AudioFormat::").to_string()
+}
+
+/// Prints all entries sorted by ID as a table or as newline-delimited JSON.
+fn print_entries(state: &State, format: OutputFormat) {
+    let mut entries: Vec<_> = state.devices.values().collect();
+    entries.sort_by_key(|e| e.id);
+
+    if format == OutputFormat::Json {
+        for e in entries {
+            let out = serde_json::json!({
+                "id": e.id,
+                "kind": e.kind.to_string(),
+                "name": e.name,
+                "label": e.label,
+                "device_id": e.device_id,
+                "codec": e.codec,
+                "sample_rate": e.sample_rate,
+                "sample_format": e.sample_format.map(format_sample_format),
+                "buffer_size": e.buffer_size,
+                "latency_ms": e.latency_ms,
+            });
+            println!("{out}");
+        }
+        return;
+    }
+
+    let rows: Vec<[String; 10]> = entries
+        .iter()
+        .map(|e| {
+            [
+                e.id.to_string(),
+                e.kind.to_string(),
+                e.name.clone().unwrap_or_default(),
+                e.label.clone().unwrap_or_default(),
+                e.device_id.map(|id| id.to_string()).unwrap_or_default(),
+                e.codec.clone().unwrap_or_default(),
+                e.sample_rate.map(|v| v.to_string()).unwrap_or_default(),
+                e.sample_format
+                    .map(format_sample_format)
+                    .unwrap_or_default(),
+                e.buffer_size.map(|v| v.to_string()).unwrap_or_default(),
+                e.latency_ms.map(|v| format!("{v:.2}")).unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let header = [
+        "ID",
+        "KIND",
+        "NAME",
+        "LABEL",
+        "DEVICE_ID",
+        "CODEC",
+        "RATE",
+        "FORMAT",
+        "BUFFER",
+        "LATENCY_MS",
+    ]
+    .map(String::from);
+    let mut widths = header.clone().map(|v| v.len());
+    for row in &rows {
+        for (w, v) in widths.iter_mut().zip(row) {
+            *w = (*w).max(v.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(v, w)| format!("{v:<w$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Sends notification in background.
+///
+/// Result is handled by [`complete_notification`] once send task is finished.
+fn send_notification(state: &mut State, notifier: &Arc<dyn Notifier>, oid: u32, msg: Message) {
+    if let Some(queued) = state.notifications_in_flight.get_mut(&oid) {
+        // Previous notification is still being sent, show the latest message after it.
+        *queued = Some(msg);
+        return;
+    }
+
+    state.notifications_in_flight.insert(oid, None);
+    let handle = state.notifications.shift_remove(&oid);
+    let notifier = notifier.clone();
+    state.pending_notifications.spawn(async move {
+        let result = match handle {
+            Some(handle) => notifier.update(handle, msg).await,
+            None => notifier.show(msg).await,
+        };
+
+        (oid, result)
     });
+}
 
-    let mut notification = Notification::new();
-
-    match (vol.mute, val) {
-        (Some(is_muted), _) if is_muted => {
-            let s = format!("{} - Muted", entry.get_label());
-            notification
-                .summary(s.as_str())
-                .icon("audio-volume-muted-symbolic");
-        }
-        (_, Some(value)) => {
-            let v = value.round() as i32;
-            let s = format!("{} - {}%", entry.get_label(), v);
-            notification
-                .summary(s.as_str())
-                .icon("audio-volume-high-symbolic")
-                .hint(Hint::CustomInt("value".to_string(), v));
-        }
-        _ => {
-            error!(
-                is_muted = vol.mute,
-                ?val,
-                entry_id = entry.id,
-                "can't send notification as no mute or volume info"
-            );
-            return None;
+/// Stores handle of a sent notification and sends a message queued while it was in flight.
+async fn complete_notification(
+    state: &mut State,
+    notifier: &Arc<dyn Notifier>,
+    cfg: &ActionConfig,
+    oid: u32,
+    result: NotifyResult,
+) {
+    let queued = state.notifications_in_flight.remove(&oid).flatten();
+    match result {
+        Ok(handle) => {
+            state.notification_failure_streak = 0;
+            if !state.devices.contains_key(&oid) {
+                // Entry was removed while notification was being sent.
+                notifier.close(handle).await;
+                return;
+            }
+
+            if let Some((evicted_oid, evicted)) =
+                state.insert_notification(oid, handle, cfg.max_open_notifications)
+            {
+                warn!(
+                    oid = evicted_oid,
+                    max = cfg.max_open_notifications,
+                    "too many open notifications, closing the oldest one"
+                );
+                notifier.close(evicted).await;
+            }
+        }
+        Err(kind) => {
+            error!(oid, "Failed to send notification: {kind}");
+            state.notification_failure_streak += 1;
+            if state.notification_failure_streak == NOTIFICATION_FAILURE_STREAK_WARN {
+                warn!(
+                    failures = state.notification_failure_streak,
+                    "Notifications keep failing, check if notification daemon is running"
+                );
+            }
         }
     }
 
-    notification
-        .urgency(notify_rust::Urgency::Normal)
-        .timeout(std::time::Duration::from_secs(5));
+    if let Some(msg) = queued {
+        send_notification(state, notifier, oid, msg);
+    }
+}
+
+/// Number of PipeWire errors after which the daemon gives up.
+const PW_ERROR_COUNT_THRESHOLD: u32 = 10;
 
-    Some(notification)
+/// ShutdownReason describes why the daemon's main loop has to stop.
+#[derive(Debug)]
+struct ShutdownReason {
+    reason: &'static str,
+
+    /// Daemon can't continue and should exit with an error.
+    is_failure: bool,
 }
 
-async fn show_volume_notification(notification: Notification) -> Option<NotificationHandle> {
-    notification
-        .show_async()
-        .await
-        .inspect_err(|err| error!("Failed to send notification: {err}"))
-        .ok()
+/// ActionConfig controls how actions are reacted on.
+#[derive(Debug, Clone)]
+struct ActionConfig {
+    /// Close notification of a removed entry.
+    ///
+    /// If disabled, notification stays visible until its timeout.
+    close_notification_on_remove: bool,
+
+    /// Show "<device> disconnected" notification when entry is removed.
+    show_remove_notification: bool,
+
+    /// Show notification when Bluetooth codec of an entry changes.
+    show_codec_notification: bool,
+
+    /// Show notification when sample rate of an entry changes.
+    show_format_notification: bool,
+
+    /// Volume changes to notify about.
+    threshold: ThresholdConfig,
+
+    /// Max number of notifications kept open.
+    ///
+    /// Guards against handles left behind by missed removals.
+    max_open_notifications: usize,
+
+    /// Remember volume of each entry and restore it when entry appears again,
+    /// e.g. after PipeWire restart.
+    restore_volumes: bool,
+
+    /// Time to show notification for.
+    notification_timeout: Duration,
+
+    /// Notification timeouts by entry name or label.
+    device_timeouts: HashMap<String, Duration>,
+    icon_style: config::IconStyle,
+    icon_thresholds: config::IconThresholds,
+
+    /// Show per-channel volumes in notification body.
+    show_channel_detail: bool,
+
+    /// Keep volume notifications out of notification history.
+    transient_notifications: bool,
+
+    /// Min interval between volume notifications of an entry.
+    ///
+    /// Changes within the interval are merged into a single notification
+    /// with the latest volume. Disabled if zero.
+    debounce: Duration,
+
+    /// Min interval between identical volume notifications of an entry.
+    notify_cooldown: Duration,
 }
 
-async fn update_volume_notification(
-    mut handle: NotificationHandle,
-    notification: Notification,
-) -> Option<NotificationHandle> {
-    tokio::task::spawn_blocking(move || {
-        *handle = notification;
-        handle.update();
-        handle
-    })
-    .await
-    .inspect_err(|err| error!("Failed to update notification: {err}"))
-    .ok()
+impl Default for ActionConfig {
+    fn default() -> Self {
+        Self {
+            close_notification_on_remove: true,
+            show_remove_notification: false,
+            show_codec_notification: false,
+            show_format_notification: false,
+            threshold: ThresholdConfig::default(),
+            max_open_notifications: 20,
+            restore_volumes: false,
+            notification_timeout: Duration::from_secs(5),
+            device_timeouts: HashMap::new(),
+            icon_style: config::IconStyle::default(),
+            icon_thresholds: config::IconThresholds::default(),
+            show_channel_detail: false,
+            transient_notifications: false,
+            debounce: Duration::from_millis(300),
+            notify_cooldown: Duration::from_millis(500),
+        }
+    }
+}
+
+impl ActionConfig {
+    /// Overrides settings with values set in config file.
+    fn apply_config(&mut self, config: &config::Config) {
+        let opts = &config.notification;
+        if let Some(v) = opts.close_on_remove {
+            self.close_notification_on_remove = v;
+        }
+
+        if let Some(v) = opts.show_remove {
+            self.show_remove_notification = v;
+        }
+
+        if let Some(v) = opts.show_codec_change {
+            self.show_codec_notification = v;
+        }
+
+        if let Some(v) = opts.notify_format_change {
+            self.show_format_notification = v;
+        }
+
+        if let Some(v) = opts.max_open {
+            self.max_open_notifications = v;
+        }
+
+        if let Some(v) = opts.timeout_ms {
+            self.notification_timeout = Duration::from_millis(v);
+        }
+
+        if let Some(v) = opts.icon_style {
+            self.icon_style = v;
+        }
+
+        if let Some(v) = &opts.icon_thresholds {
+            self.icon_thresholds = v.clone();
+        }
+
+        if let Some(v) = opts.show_channel_detail {
+            self.show_channel_detail = v;
+        }
+
+        if let Some(v) = opts.transient {
+            self.transient_notifications = v;
+        }
+
+        if let Some(v) = opts.debounce_ms {
+            self.debounce = Duration::from_millis(v);
+        }
+
+        if let Some(v) = opts.cooldown_ms {
+            self.notify_cooldown = Duration::from_millis(v);
+        }
+
+        self.device_timeouts.extend(
+            opts.device_timeouts_ms
+                .iter()
+                .map(|(name, ms)| (name.clone(), Duration::from_millis(*ms))),
+        );
+
+        if let Some(threshold) = &config.threshold {
+            self.threshold = threshold.clone();
+        }
+
+        if let Some(v) = config.restore_volumes {
+            self.restore_volumes = v;
+        }
+    }
+
+    /// Returns time to show notification of an entry for.
+    fn notification_timeout_for(&self, entry: &Entry) -> Duration {
+        [entry.name.as_deref(), entry.label.as_deref()]
+            .into_iter()
+            .flatten()
+            .find_map(|key| self.device_timeouts.get(key))
+            .copied()
+            .unwrap_or(self.notification_timeout)
+    }
+}
+
+fn build_remove_notification(entry: &Entry, cfg: &ActionConfig) -> Message {
+    Message {
+        summary: format!("{} disconnected", entry.format_label()),
+        body: None,
+        icon: cfg.icon_style.apply("audio-card-symbolic"),
+        value: None,
+        timeout: cfg.notification_timeout_for(entry),
+        transient: false,
+    }
+}
+
+fn build_codec_notification(entry: &Entry, old: &str, new: &str, cfg: &ActionConfig) -> Message {
+    Message {
+        summary: format!(
+            "{}: {} → {}",
+            entry.format_label(),
+            old.to_uppercase(),
+            new.to_uppercase()
+        ),
+        body: None,
+        icon: cfg.icon_style.apply("audio-headphones-bluetooth-symbolic"),
+        value: None,
+        timeout: cfg.notification_timeout_for(entry),
+        transient: false,
+    }
+}
+
+fn build_format_notification(entry: &Entry, old: u32, new: u32, cfg: &ActionConfig) -> Message {
+    Message {
+        summary: format!("{}: {old} Hz → {new} Hz", entry.format_label()),
+        body: None,
+        icon: cfg.icon_style.apply("audio-card-symbolic"),
+        value: None,
+        timeout: cfg.notification_timeout_for(entry),
+        transient: false,
+    }
 }
 
-async fn close_notification(handle: NotificationHandle) {
-    let _ = tokio::task::spawn_blocking(move || handle.close())
-        .await
-        .inspect_err(|err| error!("Failed to close notification: {err}"));
+/// Sends volume notification and starts debounce window of an entry.
+fn send_volume_notification(
+    state: &mut State,
+    notifier: &Arc<dyn Notifier>,
+    cfg: &ActionConfig,
+    oid: u32,
+    vol: VolumeInfo,
+    msg: Message,
+) {
+    state.set_notified_volume(oid, vol, msg.clone());
+    send_notification(state, notifier, oid, msg);
+    if !cfg.debounce.is_zero() {
+        state
+            .debounce_deadlines
+            .insert(oid, Instant::now() + cfg.debounce);
+    }
+}
+
+/// Sends volume notifications postponed by debounce windows which have ended.
+fn flush_debounced_volumes(state: &mut State, notifier: &Arc<dyn Notifier>, cfg: &ActionConfig) {
+    for (oid, vol) in state.take_debounced_volumes(Instant::now()) {
+        let Some(msg) = state
+            .devices
+            .get(&oid)
+            .and_then(|e| build_volume_notification(e, &vol, cfg))
+        else {
+            continue;
+        };
+
+        debug!(oid, ?vol, "sending debounced volume notification");
+        send_volume_notification(state, notifier, cfg, oid, vol, msg);
+    }
+}
+
+/// Closes all notifications and clears the state.
+async fn release_state(state: &mut State, notifier: &Arc<dyn Notifier>) {
+    while let Some(res) = state.pending_notifications.join_next().await {
+        if let Ok((_, Ok(handle))) = res {
+            notifier.close(handle).await;
+        }
+    }
+
+    state.notifications_in_flight.clear();
+    for handle in state.clear_entries() {
+        notifier.close(handle).await;
+    }
 }
 
 #[cfg(target_os = "linux")]
-#[tracing::instrument(name = "handle_action", skip(state, msg))]
-async fn handle_action(state: &mut State, msg: ActionType) {
+#[tracing::instrument(name = "handle_action", skip(state, notifier, cfg, msg))]
+async fn handle_action(
+    state: &mut State,
+    notifier: &Arc<dyn Notifier>,
+    cfg: &ActionConfig,
+    msg: ActionType,
+) -> ControlFlow<ShutdownReason> {
+    if let Some(oid) = msg.object_id() {
+        state.touch(oid);
+    }
+
     match msg {
-        ActionType::EntryAdd(oid, entry) => {
-            info!(oid, ?entry, "EntryAdd");
+        ActionType::EntryAdd(oid, mut entry) => {
+            let old_label = entry.label.clone();
+            if let Some(device) = entry
+                .device_id
+                .and_then(|id| state.find_by_global_id(id))
+                .and_then(|dev_oid| state.devices.get(&dev_oid))
+                && entry.merge_with_device_info(device)
+            {
+                debug!(
+                    oid,
+                    ?old_label,
+                    new_label = entry.label,
+                    "replaced generic node label with device description"
+                );
+            }
+
+            if let Some(client) = entry.app_pid.and_then(|pid| state.find_client_by_pid(pid)) {
+                entry.apply_client_info(client);
+            }
+
+            entry.update_buffer_size(state.clock_quantum);
+            info!(oid, %entry, "EntryAdd");
             state.devices.insert(oid, entry);
         }
-        ActionType::VolumeChange(oid, vol) => match state.devices.get_mut(&oid) {
-            // TODO: check if state has not changed (regression when opening pamixer).
-            Some(e) if e.volume.is_none() => {
+        ActionType::EntryUpdate(oid, mut entry) => {
+            // Props updates carry raw node labels, keep them in line with EntryAdd.
+            if let Some(device) = entry
+                .device_id
+                .and_then(|id| state.find_by_global_id(id))
+                .and_then(|dev_oid| state.devices.get(&dev_oid))
+            {
+                entry.merge_with_device_info(device);
+            }
+
+            match state.devices.get_mut(&oid) {
+                Some(e) => {
+                    let old_codec = e.codec.clone();
+                    let changed = e.update_props(entry);
+                    debug!(oid, entry_name = %e.format_label(), ?changed, "EntryUpdate");
+                    if changed.contains(&"node_latency") {
+                        e.update_buffer_size(state.clock_quantum);
+                    }
+
+                    if !changed.contains(&"codec") {
+                        return ControlFlow::Continue(());
+                    }
+
+                    info!(
+                        oid,
+                        entry_name = %e.format_label(),
+                        ?old_codec,
+                        new_codec = e.codec,
+                        "bluetooth codec changed"
+                    );
+                    if let (true, Some(old), Some(new)) =
+                        (cfg.show_codec_notification, old_codec, e.codec.as_deref())
+                    {
+                        let msg = build_codec_notification(e, &old, new, cfg);
+                        send_notification(state, notifier, oid, msg);
+                    }
+                }
+                None => {
+                    warn!(oid, "got EntryUpdate event for orphan device/node");
+                }
+            }
+        }
+        ActionType::VolumeChangeRaw(oid, vol) => match state.devices.get_mut(&oid) {
+            Some(e) => {
                 // After initial subscribe - first message is fired immediately to send a current
                 // state.
                 debug!(
                     oid,
-                    entry_name = e.get_label(),
+                    entry_name = %e.format_label(),
                     ?vol,
                     "received volume info for first time, skip notification"
                 );
 
-                e.volume = Some(vol);
+                e.update_volume(vol);
+            }
+            None => {
+                warn!(oid, "got VolumeChangeRaw event for orphan device/node");
+            }
+        },
+        ActionType::VolumeChangeWithDelta(oid, vol, delta) => {
+            let is_significant = state
+                .is_significant_volume_change(oid, &vol, &cfg.threshold)
+                .unwrap_or_else(|| delta.is_significant(cfg.threshold.min_volume_change_pct));
+
+            let Some(e) = state.devices.get_mut(&oid) else {
+                warn!(
+                    oid,
+                    "got VolumeChangeWithDelta event for orphan device/node"
+                );
+                return ControlFlow::Continue(());
+            };
+
+            if !is_significant {
+                // skip duplicate event fired when playback/resume happens
+                info!(
+                    oid,
+                    entry_name = %e.format_label(),
+                    ?vol,
+                    "volume didn't change, skip"
+                );
+                e.update_volume(vol);
+
+                // Volume returned close to the notified value, postponed one is outdated.
+                state.pending_volumes.remove(&oid);
+                return ControlFlow::Continue(());
             }
+
+            info!(
+                oid,
+                entry_name = %e.format_label(),
+                ?vol,
+                direction = ?delta.direction(),
+                "VolumeChange"
+            );
+
+            // Saved volumes are restored only for nodes, see VolumeInfo::to_spa_props_pod.
+            if let (Some(store), Some(name)) = (state.volume_store.as_mut(), e.name.as_deref())
+                && e.is_node
+                && let Err(err) = store.save(name, &vol)
+            {
+                warn!(oid, "failed to save volume: {err:#}");
+            }
+
+            let notification = match build_volume_notification(e, &vol, cfg) {
+                Some(notification) => notification,
+                None => {
+                    if let Some(handle) = state.notifications.shift_remove(&oid) {
+                        notifier.close(handle).await;
+                    }
+                    e.update_volume(vol);
+                    return ControlFlow::Continue(());
+                }
+            };
+
+            // Some drivers keep re-emitting the same props.
+            let is_repeated = state
+                .last_notified
+                .get(&oid)
+                .is_some_and(|last| last.is_repeated_by(&notification, cfg.notify_cooldown));
+            if is_repeated {
+                debug!(oid, "skip notification identical to a recent one");
+                e.update_volume(vol);
+
+                // Volume returned to the notified value, postponed one is outdated.
+                state.pending_volumes.remove(&oid);
+                return ControlFlow::Continue(());
+            }
+
+            let is_debounced = state
+                .debounce_deadlines
+                .get(&oid)
+                .is_some_and(|deadline| *deadline > Instant::now());
+            if is_debounced {
+                // Notification is sent with the latest volume once debounce window ends.
+                debug!(oid, "volume notification postponed by debounce");
+                e.update_volume(vol.clone());
+                state.pending_volumes.insert(oid, vol);
+                return ControlFlow::Continue(());
+            }
+
+            e.update_volume(vol.clone());
+            send_volume_notification(state, notifier, cfg, oid, vol, notification);
+        }
+        ActionType::FormatChanged(oid, fmt) => match state.devices.get_mut(&oid) {
             Some(e) => {
-                if let Some(current) = e.volume.as_ref()
-                    && current == &vol
+                info!(oid, entry_name = %e.format_label(), ?fmt, "FormatChanged");
+                let old_rate = std::mem::replace(&mut e.sample_rate, fmt.sample_rate);
+                e.sample_format = fmt.sample_format;
+                e.update_latency_ms();
+
+                // Initial format is reported right after subscription.
+                if let (true, Some(old), Some(new)) =
+                    (cfg.show_format_notification, old_rate, e.sample_rate)
+                    && old != new
                 {
-                    // skip duplicate event fired when playback/resume happens
-                    info!(
-                        oid,
-                        entry_name = e.get_label(),
-                        ?vol,
-                        "volume didn't change, skip"
-                    );
-                    return;
+                    let msg = build_format_notification(e, old, new, cfg);
+                    send_notification(state, notifier, oid, msg);
                 }
+            }
+            None => {
+                warn!(oid, "got FormatChanged event for orphan device/node");
+            }
+        },
+        ActionType::QuantumChanged(quantum) => {
+            info!(quantum, "QuantumChanged");
+            state.clock_quantum = Some(quantum);
+            for e in state.devices.values_mut() {
+                e.update_buffer_size(state.clock_quantum);
+            }
+        }
+        ActionType::GlobalRemove(global_id) => {
+            // Registry reports removal of all objects, skip untracked ones.
+            let Some(oid) = state.find_by_global_id(global_id) else {
+                debug!(global_id, "skip removal of untracked object");
+                return ControlFlow::Continue(());
+            };
 
-                info!(oid, entry_name = e.get_label(), ?vol, "VolumeChange");
+            let (handle, entry) = state.remove_entry(&oid);
+            info!(
+                oid,
+                entry = entry.as_ref().map(ToString::to_string),
+                "GlobalRemove"
+            );
+            let msg = entry
+                .as_ref()
+                .filter(|_| cfg.show_remove_notification)
+                .map(|e| build_remove_notification(e, cfg));
 
-                let notification = match build_volume_notification(e, &vol) {
-                    Some(notification) => notification,
-                    None => {
-                        if let Some(handle) = state.notifications.remove(&oid) {
-                            close_notification(handle).await;
-                        }
-                        e.volume = Some(vol);
-                        return;
-                    }
-                };
+            match (handle, msg) {
+                (handle, Some(msg)) => {
+                    // Notification isn't tracked as entry is gone.
+                    let result = match handle {
+                        Some(handle) => notifier.update(handle, msg).await,
+                        None => notifier.show(msg).await,
+                    };
 
-                if let Some(handle) = state.notifications.remove(&oid) {
-                    if let Some(updated) = update_volume_notification(handle, notification).await {
-                        state.notifications.insert(oid, updated);
+                    if let Err(kind) = result {
+                        error!(oid, "Failed to send notification: {kind}");
                     }
-                } else if let Some(handle) = show_volume_notification(notification).await {
-                    state.notifications.insert(oid, handle);
+                }
+                (Some(handle), None) if cfg.close_notification_on_remove => {
+                    notifier.close(handle).await;
+                }
+                _ => {}
+            }
+        }
+        ActionType::StartupStats(stats) => {
+            info!(
+                by_type = ?stats.by_type,
+                "Startup: saw {} objects, registered {} audio objects, ignored {}, {} errors",
+                stats.seen,
+                stats.parsed.saturating_sub(stats.ignored),
+                stats.ignored,
+                stats.errors
+            );
+            state.startup_stats = Some(stats);
+
+            if let Some(snapshot) = state.reconnect_snapshot.take() {
+                let mapping = state.map_entries_by_name(&snapshot);
+                info!(
+                    mapped = mapping.len(),
+                    "restoring volumes known before reconnect"
+                );
+                state.merge_from_snapshot(&snapshot, mapping);
+            }
+        }
+        ActionType::LinkAdd(link) => {
+            debug!(?link, "LinkAdd");
+            state.links.insert(link.id, link);
+        }
+        ActionType::LinkStateChanged(id, link_state) => match state.links.get_mut(&id) {
+            Some(link) => {
+                if link_state == LinkState::Active && link.state != LinkState::Active {
+                    info!(
+                        link_id = id,
+                        output_node = link.output_node,
+                        input_node = link.input_node,
+                        "link became active"
+                    );
                 }
 
-                e.volume = Some(vol);
+                link.state = link_state;
             }
             None => {
-                warn!(oid, "got VolumeChange event for orphan device/node");
+                warn!(link_id = id, "got LinkStateChanged event for unknown link");
             }
         },
-        ActionType::EntryRemove(oid) => match state.devices.get(&oid) {
-            Some(entry) => {
-                info!(oid, ?entry, "EntryRemove");
-                if let Some(handle) = state.remove_entry(&oid) {
-                    close_notification(handle).await;
+        ActionType::LinkRemove(id) => {
+            let link = state.links.remove(&id);
+            debug!(link_id = id, ?link, "LinkRemove");
+        }
+        ActionType::ClientAdd(id, client) => {
+            debug!(client_id = id, ?client, "ClientAdd");
+            for (oid, e) in state.devices.iter_mut() {
+                if e.apply_client_info(&client) {
+                    debug!(
+                        oid,
+                        entry_name = %e.format_label(),
+                        "ClientAdd: app name updated"
+                    );
                 }
             }
-            None => {
-                warn!(oid, "got VolumeChange event for orphan device/node");
+
+            state.clients.insert(id, client);
+        }
+        ActionType::ClientRemove(id) => {
+            let client = state.clients.remove(&id);
+            debug!(client_id = id, ?client, "ClientRemove");
+        }
+        ActionType::DefaultChanged(kind, name) => {
+            info!(?kind, name, "DefaultChanged");
+            if kind == DefaultKind::Sink {
+                state.default_sink = Some(name);
             }
-        },
-        ActionType::Shutdown => {
-            for handle in state.clear_entries() {
-                close_notification(handle).await;
+        }
+        ActionType::PipeWireVersion(version) => {
+            info!(version, "PipeWireVersion");
+            state.pw_version = Some(version);
+        }
+        ActionType::Error(id, kind) => {
+            state.pw_error_count += 1;
+            if kind.is_fatal {
+                error!(obj_id = id, "PipeWire error: {}", kind.msg);
+            } else {
+                warn!(obj_id = id, "PipeWire error: {}", kind.msg);
             }
+
+            let reason = if kind.is_fatal {
+                "pipewire connection is broken"
+            } else if state.pw_error_count > PW_ERROR_COUNT_THRESHOLD {
+                "too many pipewire errors"
+            } else {
+                return ControlFlow::Continue(());
+            };
+
+            release_state(state, notifier).await;
+            return ControlFlow::Break(ShutdownReason {
+                reason,
+                is_failure: true,
+            });
+        }
+        ActionType::Disconnected => {
+            info!("Disconnected");
+
+            // Entries get new IDs after reconnect, they are matched by name later.
+            // Snapshot of a previous connection is kept if it was lost before resync.
+            if !state.devices.is_empty() {
+                let snapshot = State {
+                    devices: state.devices.clone(),
+                    ..Default::default()
+                };
+                state.reconnect_snapshot = Some(Box::new(snapshot));
+            }
+            release_state(state, notifier).await;
+            state.links.clear();
+            state.clients.clear();
+            state.pw_error_count = 0;
+        }
+        ActionType::Shutdown => {
+            release_state(state, notifier).await;
             info!("bye!");
+            return ControlFlow::Break(ShutdownReason {
+                reason: "pipewire listener stopped",
+                is_failure: false,
+            });
+        }
+    }
+
+    ControlFlow::Continue(())
+}
+
+type StateFactory = Box<dyn FnOnce() -> State>;
+
+/// Starts a listener which produces actions for the main loop until cancelled.
+type ListenerFactory = Box<
+    dyn FnOnce(oneshot::Receiver<()>, pwloop::ListenerConfig) -> Result<pwloop::ActionListener>,
+>;
+
+/// RunContext contains dependencies used by the daemon's main loop.
+struct RunContext {
+    /// Loaded config file, or defaults if there's none.
+    config: Arc<config::Config>,
+    notifier: Arc<dyn Notifier>,
+    listener_config: pwloop::ListenerConfig,
+
+    /// Starts PipeWire listener, see [pwloop::start_pw_thread].
+    listener_factory: ListenerFactory,
+
+    /// Constructs initial daemon state.
+    state_factory: StateFactory,
+
+    /// Print the first volume change in specified format and exit.
+    once: Option<OutputFormat>,
+
+    /// Print volume of the last changed sink in specified format on every change
+    /// and on `SIGUSR1`.
+    watch: Option<OutputFormat>,
+
+    /// Remove entries without any events for specified time.
+    ///
+    /// Idle devices don't produce events either, so eviction is disabled by default.
+    stale_device_max_age: Option<Duration>,
+    action_config: ActionConfig,
+
+    /// Delivers received actions to other consumers.
+    event_bus: EventBus,
+
+    /// Log every received action. Used along with a notifier which doesn't display anything.
+    dry_run: bool,
+
+    /// Print entries in specified format after initial sync with PipeWire and exit.
+    list_devices: Option<OutputFormat>,
+
+    /// Config file specified on command line.
+    ///
+    /// Instances with different config files use separate PID files.
+    config_path: Option<PathBuf>,
+}
+
+impl Default for RunContext {
+    fn default() -> Self {
+        let mut listener_config = pwloop::ListenerConfig::default();
+        listener_config
+            .set_ignore_list(vec!["easyeffects_sink".to_string()])
+            .expect("default ignore list is valid");
+
+        Self {
+            config: Arc::default(),
+            notifier: notifier::new_notifier(),
+            listener_config,
+            listener_factory: Box::new(pwloop::start_pw_thread),
+            state_factory: Box::new(State::default),
+            once: None,
+            watch: None,
+            stale_device_max_age: None,
+            action_config: ActionConfig::default(),
+            event_bus: EventBus::default(),
+            dry_run: false,
+            list_devices: None,
+            config_path: None,
         }
     }
 }
 
-#[tracing::instrument(name = "run")]
-async fn run() -> Result<()> {
+impl RunContext {
+    /// Returns context with settings overridden by config file.
+    fn from_config(config: Arc<config::Config>) -> Result<Self> {
+        let mut ctx = Self {
+            config: config.clone(),
+            ..Self::default()
+        };
+        ctx.listener_config.apply_config(&config.listener)?;
+        ctx.action_config.apply_config(&config);
+        if config.notification.backend.is_some()
+            || config.notification_update_timeout_secs.is_some()
+        {
+            let backend = config.notification.backend.clone().unwrap_or_default();
+            let call_timeout = config
+                .notification_update_timeout_secs
+                .map_or(notifier::DEFAULT_CALL_TIMEOUT, Duration::from_secs);
+            ctx.notifier = notifier::create_notifier(&backend, call_timeout);
+        }
+
+        Ok(ctx)
+    }
+}
+
+/// Opens volume store at default location.
+///
+/// Volumes aren't persisted if store can't be opened.
+fn open_volume_store() -> Option<volumestore::VolumeStore> {
+    let Some(path) = volumestore::VolumeStore::default_path() else {
+        warn!("neither XDG_DATA_HOME nor HOME is set, volumes won't be restored");
+        return None;
+    };
+
+    volumestore::VolumeStore::open(path)
+        .inspect_err(|err| error!("failed to open volume store: {err:#}"))
+        .ok()
+}
+
+#[tracing::instrument(name = "run", skip(ctx))]
+async fn run(ctx: RunContext) -> Result<()> {
     let span = info_span!("msg_listener");
     let _h = span.enter();
-    let mut listen_cfg = pwloop::ListenerConfig::default();
-    listen_cfg.set_ignore_list(vec!["easyeffects_sink".to_string()]);
 
+    // Modes other than the daemon itself can run alongside the daemon and don't change volumes.
+    let is_secondary =
+        ctx.once.is_some() || ctx.watch.is_some() || ctx.dry_run || ctx.list_devices.is_some();
+    let pid_path = match &ctx.config_path {
+        Some(path) => pidfile::PidFile::path_for_config(path),
+        None => pidfile::PidFile::default_path(),
+    };
+    let _pid_file = match pid_path {
+        _ if is_secondary || !ctx.config.create_pid_file.unwrap_or(true) => None,
+        Some(path) => Some(pidfile::PidFile::create(path)?),
+        None => {
+            warn!("XDG_RUNTIME_DIR is not set, skip pid file creation");
+            None
+        }
+    };
+
+    let RunContext {
+        notifier,
+        mut listener_config,
+        listener_factory,
+        state_factory,
+        mut once,
+        watch,
+        stale_device_max_age,
+        action_config,
+        event_bus,
+        dry_run,
+        list_devices,
+        ..
+    } = ctx;
+
+    let volume_store = if action_config.restore_volumes && !is_secondary {
+        open_volume_store()
+    } else {
+        None
+    };
+    listener_config.set_volume_store(volume_store.clone());
+
+    let max_message_age = listener_config.max_message_age();
     let (stop_tx, stop_rx) = oneshot::channel::<()>();
-    let mut h = pwloop::start_pw_thread(stop_rx, listen_cfg)
-        .context("failed to start pipewire listener")?;
-    let shutdown_signal = tokio::signal::ctrl_c();
+    let mut h =
+        listener_factory(stop_rx, listener_config).context("failed to start pipewire listener")?;
+
+    // systemd stops services with SIGTERM.
+    let mut terminate_signal =
+        signal(SignalKind::terminate()).context("failed to subscribe to SIGTERM")?;
+    let shutdown_signal = async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate_signal.recv() => "SIGTERM",
+        }
+    };
     tokio::pin!(shutdown_signal);
 
-    let mut state = State::default();
+    // Waybar sends a signal to request an update.
+    let mut refresh_signal =
+        signal(SignalKind::user_defined1()).context("failed to subscribe to SIGUSR1")?;
+    let mut watched_oid = None;
+
+    let mut state = state_factory();
+    state.volume_store = volume_store;
+    let mut stop_tx = Some(stop_tx);
+    let mut evict_interval = tokio::time::interval(STALE_DEVICES_CHECK_INTERVAL);
+
+    // Interval isn't polled if watchdog is disabled.
+    let watchdog_interval = sdnotify::watchdog_interval();
+    let mut watchdog_tick =
+        tokio::time::interval(watchdog_interval.unwrap_or(STALE_DEVICES_CHECK_INTERVAL));
+    let mut last_message_at = Instant::now();
+    let mut is_listener_silent = false;
+
+    sdnotify::notify_ready();
     loop {
+        let debounce_deadline = state.next_debounce_deadline();
         tokio::select! {
-            _ = &mut shutdown_signal => {
-                let _ = stop_tx.send(());
-                break;
+            _ = tokio::time::sleep_until(debounce_deadline.unwrap_or_else(Instant::now).into()),
+                if debounce_deadline.is_some() =>
+            {
+                flush_debounced_volumes(&mut state, &notifier, &action_config);
+            },
+            _ = watchdog_tick.tick(), if watchdog_interval.is_some() => {
+                // PipeWire is silent when idle, so only the main loop liveness is reported.
+                let silence = last_message_at.elapsed();
+                if !is_listener_silent && silence > watchdog_interval.unwrap_or_default() * 2 {
+                    warn!(
+                        silence_secs = silence.as_secs(),
+                        "no events from pipewire listener for two watchdog intervals"
+                    );
+                    is_listener_silent = true;
+                }
+
+                sdnotify::kick_watchdog();
+            },
+            _ = evict_interval.tick(), if stale_device_max_age.is_some() => {
+                let max_age = stale_device_max_age.unwrap_or_default();
+                for (oid, entry) in state.evict_stale_devices(max_age) {
+                    info!(oid, entry = entry.format_label_long(), "evicted stale entry");
+                    if let Some(handle) = state.notifications.shift_remove(&oid) {
+                        notifier.close(handle).await;
+                    }
+                }
+            },
+            signal_name = &mut shutdown_signal, if stop_tx.is_some() => {
+                info!(signal = signal_name, "received shutdown signal");
+
+                // Wait for listener to send Shutdown event to release resources.
+                if let Some(tx) = stop_tx.take() {
+                    let _ = tx.send(());
+                }
+            },
+            Some(_) = refresh_signal.recv(), if watch.is_some() => {
+                if let (Some(format), Some(entry)) =
+                    (watch, watched_oid.and_then(|oid| state.devices.get(&oid)))
+                {
+                    print_volume_change(entry, format, &action_config.icon_thresholds);
+                }
+            },
+            Some(res) = state.pending_notifications.join_next() => match res {
+                Ok((oid, result)) => {
+                    complete_notification(&mut state, &notifier, &action_config, oid, result).await;
+                }
+                Err(err) => error!("notification task failed: {err}"),
             },
-            Some(msg) = h.recv() => {
-                handle_action(&mut state, msg).await;
+            msg = h.recv() => {
+                let Some(msg) = msg else {
+                    warn!("pipewire listener channel closed");
+                    break;
+                };
+
+                last_message_at = Instant::now();
+                is_listener_silent = false;
+
+                // Stale volume values are misleading, next event will carry an actual value.
+                let age = msg.age();
+                if msg.action.is_volume_change() && age > max_message_age {
+                    debug!(age_ms = age.as_millis(), action = ?msg.action, "drop stale action");
+                    continue;
+                }
+
+                let once_oid = match &msg.action {
+                    ActionType::VolumeChangeWithDelta(oid, _, delta)
+                        if delta.is_significant(VOLUME_CHANGE_THRESHOLD) =>
+                    {
+                        Some(*oid)
+                    }
+                    _ => None,
+                };
+
+                let watch_oid = match &msg.action {
+                    ActionType::VolumeChangeRaw(oid, _) if watched_oid.is_none() => Some(*oid),
+                    ActionType::VolumeChangeWithDelta(oid, ..) => Some(*oid),
+                    ActionType::DefaultChanged(DefaultKind::Sink, name) => state.find_by_name(name),
+                    _ => None,
+                };
+
+                if dry_run {
+                    info!(action = ?msg.action, "dry run: received action");
+                }
+
+                event_bus.publish(&msg.action);
+                let is_synced = matches!(msg.action, ActionType::StartupStats(_));
+                let flow = handle_action(&mut state, &notifier, &action_config, msg.action).await;
+                if let Some(format) = list_devices
+                    && is_synced
+                {
+                    print_entries(&state, format);
+                    if let Some(tx) = stop_tx.take() {
+                        let _ = tx.send(());
+                    }
+                }
+
+                if let (Some(format), Some(oid)) = (once, once_oid)
+                    && let Some(entry) = state.devices.get(&oid)
+                {
+                    print_volume_change(entry, format, &action_config.icon_thresholds);
+                    once = None;
+                    if let Some(tx) = stop_tx.take() {
+                        let _ = tx.send(());
+                    }
+                }
+
+                if let (Some(format), Some(oid)) = (watch, watch_oid)
+                    && let Some(entry) = state.devices.get(&oid)
+                    && entry.kind == DeviceKind::Sink
+                    && state
+                        .default_sink
+                        .as_ref()
+                        .is_none_or(|name| entry.name.as_ref() == Some(name))
+                {
+                    print_volume_change(entry, format, &action_config.icon_thresholds);
+                    watched_oid = Some(oid);
+                }
+
+                if let ControlFlow::Break(r) = flow {
+                    if r.is_failure {
+                        bail!("{}", r.reason);
+                    }
+
+                    info!(reason = r.reason, "stopping");
+                    break;
+                }
             },
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use notifier::Handle;
+    use std::sync::Mutex;
+
+    /// Max time for the main loop to handle test actions.
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// RecordingNotifier stores shown and updated notifications.
+    #[derive(Default)]
+    struct RecordingNotifier {
+        messages: Mutex<Vec<Message>>,
+    }
+
+    impl RecordingNotifier {
+        fn summaries(&self) -> Vec<String> {
+            let messages = self.messages.lock().unwrap();
+            messages.iter().map(|m| m.summary.clone()).collect()
+        }
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult> {
+            self.messages.lock().unwrap().push(msg);
+            Box::pin(async { Ok(Handle::Detached) })
+        }
+
+        fn update(&self, _handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult> {
+            self.show(msg)
+        }
+
+        fn close(&self, _handle: Handle) -> BoxFuture<'_, ()> {
+            Box::pin(async {})
+        }
+    }
+
+    fn sink(volume_pct: f32) -> Entry {
+        Entry {
+            id: 42,
+            is_node: true,
+            name: Some("alsa_output.pci-0000_00_1f.3.analog-stereo".to_string()),
+            label: Some("Headphones".to_string()),
+            kind: DeviceKind::Sink,
+            volume: Some(VolumeInfo::from_percentage(volume_pct, 2)),
+            ..Default::default()
+        }
+    }
+
+    /// Returns bluetooth sink built from props the same way PipeWire listener does.
+    fn bluez_sink(codec: &str) -> Entry {
+        let props = pipewire::properties::properties! {
+            "media.class" => "Audio/Sink",
+            "node.name" => "bluez_output.00_11_22_33_44_55.1",
+            "node.description" => "WH-1000XM4",
+            "api.bluez5.codec" => codec,
+        };
+        Entry::from_props(42, &pipewire::types::ObjectType::Node, props.dict()).unwrap()
+    }
+
+    /// Runs the main loop with `actions` delivered instead of PipeWire events.
+    ///
+    /// Main loop is stopped once a notification is shown.
+    async fn run_until_notified(
+        config: config::Config,
+        state: State,
+        notifier: Arc<RecordingNotifier>,
+        actions: Vec<ActionType>,
+    ) {
+        let config = config::Config {
+            create_pid_file: Some(false),
+            ..config
+        };
+        let (tx, rx) = tokio::sync::mpsc::channel(actions.len() + 1);
+        let ctx = RunContext {
+            notifier: notifier.clone(),
+            listener_factory: Box::new(move |_, _| Ok(rx)),
+            state_factory: Box::new(move || state),
+            ..RunContext::from_config(Arc::new(config)).unwrap()
+        };
+
+        let send_actions = async move {
+            for action in actions {
+                tx.send(action.into()).await.unwrap();
+            }
+
+            while notifier.messages.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+
+            tx.send(ActionType::Shutdown.into()).await.unwrap();
+        };
+
+        let (result, ()) =
+            tokio::time::timeout(TEST_TIMEOUT, async { tokio::join!(run(ctx), send_actions) })
+                .await
+                .expect("main loop didn't stop in time");
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_shows_volume_notification() {
+        let mut state = State::default();
+        state.devices.insert(42, sink(50.0));
+
+        let vol = VolumeInfo::from_percentage(60.0, 2);
+        let delta = vol.delta_from(&VolumeInfo::from_percentage(50.0, 2));
+        let notifier = Arc::new(RecordingNotifier::default());
+        run_until_notified(
+            config::Config::default(),
+            state,
+            notifier.clone(),
+            vec![ActionType::VolumeChangeWithDelta(42, vol, delta)],
+        )
+        .await;
+
+        assert_eq!(notifier.summaries(), vec!["Headphones - 60%"]);
+    }
+
+    #[tokio::test]
+    async fn run_shows_codec_notification_on_props_update() {
+        let mut config = config::Config::default();
+        config.notification.show_codec_change = Some(true);
+
+        let mut state = State::default();
+        state.devices.insert(42, bluez_sink("sbc"));
+
+        let notifier = Arc::new(RecordingNotifier::default());
+        run_until_notified(
+            config,
+            state,
+            notifier.clone(),
+            vec![ActionType::EntryUpdate(42, bluez_sink("ldac"))],
+        )
+        .await;
+
+        assert_eq!(notifier.summaries(), vec!["WH-1000XM4: SBC → LDAC"]);
+    }
+}
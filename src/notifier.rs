@@ -0,0 +1,476 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+#[cfg(feature = "dbus-notify")]
+use futures::future::FutureExt;
+
+#[cfg(not(any(feature = "dbus-notify", feature = "libnotify")))]
+compile_error!("at least one notification backend feature must be enabled: dbus-notify, libnotify");
+
+/// Message is a backend-agnostic desktop notification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub summary: String,
+
+    /// Optional details shown below the summary.
+    pub body: Option<String>,
+    pub icon: String,
+
+    /// Progress value hint (0-100). Used by some notification daemons to draw a progress bar.
+    pub value: Option<i32>,
+    pub timeout: Duration,
+
+    /// Don't keep notification in notification history.
+    pub transient: bool,
+}
+
+/// Handle is a reference to a displayed notification.
+pub enum Handle {
+    #[cfg(feature = "dbus-notify")]
+    DBus(Box<notify_rust::NotificationHandle>),
+
+    /// Notification that can't be updated or closed.
+    #[allow(dead_code)]
+    Detached,
+}
+
+impl std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "dbus-notify")]
+            Handle::DBus(h) => write!(f, "DBus({})", h.id()),
+            Handle::Detached => f.write_str("Detached"),
+        }
+    }
+}
+
+/// NotificationFailureKind describes why notification wasn't delivered.
+#[cfg_attr(not(feature = "dbus-notify"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationFailureKind {
+    /// Notification daemon isn't running or not reachable.
+    DaemonUnavailable,
+
+    /// Notification daemon rejected a notification field.
+    InvalidField(String),
+    Timeout,
+    Unknown(String),
+}
+
+impl std::fmt::Display for NotificationFailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DaemonUnavailable => f.write_str("notification daemon is unavailable"),
+            Self::InvalidField(err) => write!(f, "invalid notification field: {err}"),
+            Self::Timeout => f.write_str("notification daemon didn't reply in time"),
+            Self::Unknown(err) => f.write_str(err),
+        }
+    }
+}
+
+pub type NotifyResult = Result<Handle, NotificationFailureKind>;
+
+/// Notifier is a desktop notification backend.
+pub trait Notifier: Send + Sync {
+    /// Shows a new notification.
+    fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult>;
+
+    /// Replaces contents of already displayed notification.
+    fn update(&self, handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult>;
+
+    /// Closes a notification.
+    fn close(&self, handle: Handle) -> BoxFuture<'_, ()>;
+}
+
+/// NotificationBackend is a notification delivery method.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotificationBackend {
+    /// Send notifications over DBus. Requires `dbus-notify` feature.
+    DBus,
+
+    /// Invoke a `notify-send` compatible command. Requires `libnotify` feature.
+    Subprocess { cmd: String },
+
+    /// Write notifications as JSON lines to a Unix socket.
+    ///
+    /// Useful for custom widgets like eww or anybar.
+    Custom { socket: PathBuf },
+}
+
+impl Default for NotificationBackend {
+    /// Returns backend selected at compile time.
+    ///
+    /// `libnotify` feature takes precedence over `dbus-notify`.
+    fn default() -> Self {
+        if cfg!(feature = "libnotify") {
+            Self::Subprocess {
+                cmd: "notify-send".to_string(),
+            }
+        } else {
+            Self::DBus
+        }
+    }
+}
+
+/// Returns notifier for a backend selected at compile time.
+pub fn new_notifier() -> Arc<dyn Notifier> {
+    create_notifier(&NotificationBackend::default(), DEFAULT_CALL_TIMEOUT)
+}
+
+/// Returns notifier for a backend.
+///
+/// Falls back to the default backend if requested one isn't enabled at compile time.
+/// Call timeout is applied only to backends waiting for notification daemon reply.
+#[cfg_attr(not(feature = "dbus-notify"), allow(clippy::only_used_in_recursion))]
+pub fn create_notifier(backend: &NotificationBackend, call_timeout: Duration) -> Arc<dyn Notifier> {
+    match backend {
+        #[cfg(feature = "dbus-notify")]
+        NotificationBackend::DBus => Arc::new(DBusNotifier::with_call_timeout(call_timeout)),
+        #[cfg(feature = "libnotify")]
+        NotificationBackend::Subprocess { cmd } => Arc::new(SubprocessNotifier::new(cmd.clone())),
+        NotificationBackend::Custom { socket } => Arc::new(SocketNotifier::new(socket.clone())),
+
+        #[allow(unreachable_patterns)]
+        backend => {
+            tracing::warn!(
+                ?backend,
+                "notification backend is disabled at compile time, using default"
+            );
+            create_notifier(&NotificationBackend::default(), call_timeout)
+        }
+    }
+}
+
+/// Default time to wait for notification daemon to show or update a notification.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// DBusNotifier sends notifications over DBus using `notify-rust`.
+#[cfg(feature = "dbus-notify")]
+#[cfg_attr(feature = "libnotify", allow(dead_code))]
+#[derive(Debug)]
+pub struct DBusNotifier {
+    /// Max time to wait for notification daemon reply.
+    ///
+    /// Hung calls are abandoned along with notification handle.
+    call_timeout: Duration,
+}
+
+#[cfg(feature = "dbus-notify")]
+impl Default for DBusNotifier {
+    fn default() -> Self {
+        Self {
+            call_timeout: DEFAULT_CALL_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(feature = "dbus-notify")]
+impl DBusNotifier {
+    pub fn with_call_timeout(call_timeout: Duration) -> Self {
+        Self { call_timeout }
+    }
+
+    /// Waits for a notification daemon call to complete within [DBusNotifier::call_timeout].
+    async fn with_timeout(
+        &self,
+        op: &str,
+        call: impl Future<Output = NotifyResult>,
+    ) -> NotifyResult {
+        tokio::time::timeout(self.call_timeout, call)
+            .await
+            .unwrap_or_else(|_| {
+                tracing::warn!(
+                    timeout_ms = self.call_timeout.as_millis() as u64,
+                    "notification {op} timed out, abandoning notification"
+                );
+                Err(NotificationFailureKind::Timeout)
+            })
+    }
+
+    fn build(msg: Message) -> notify_rust::Notification {
+        let mut notification = notify_rust::Notification::new();
+        notification
+            .summary(msg.summary.as_str())
+            .icon(msg.icon.as_str())
+            .urgency(notify_rust::Urgency::Normal)
+            .timeout(msg.timeout);
+
+        if let Some(body) = &msg.body {
+            notification.body(body);
+        }
+
+        if let Some(v) = msg.value {
+            notification.hint(notify_rust::Hint::CustomInt("value".to_string(), v));
+        }
+
+        if msg.transient {
+            notification.hint(notify_rust::Hint::Transient(true));
+        }
+
+        notification
+    }
+
+    /// Classifies notify-rust error.
+    ///
+    /// notify-rust doesn't expose error kind, so classification relies on DBus error names
+    /// and messages.
+    fn classify_error(err: notify_rust::error::Error) -> NotificationFailureKind {
+        let msg = err.to_string();
+        let has = |patterns: &[&str]| patterns.iter().any(|p| msg.contains(p));
+
+        if has(&[
+            "ServiceUnknown",
+            "NameHasNoOwner",
+            "No such file or directory",
+            "Connection refused",
+        ]) {
+            NotificationFailureKind::DaemonUnavailable
+        } else if has(&["NoReply", "Timeout", "timed out"]) {
+            NotificationFailureKind::Timeout
+        } else if has(&["InvalidArgs", "Conversion Error", "Parsing Error"]) {
+            NotificationFailureKind::InvalidField(msg)
+        } else {
+            NotificationFailureKind::Unknown(msg)
+        }
+    }
+}
+
+#[cfg(feature = "dbus-notify")]
+impl Notifier for DBusNotifier {
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "dbus", summary = msg.summary))]
+    fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            let call = async {
+                Self::build(msg)
+                    .show_async()
+                    .await
+                    .map(|handle| Handle::DBus(Box::new(handle)))
+                    .map_err(Self::classify_error)
+            };
+
+            self.with_timeout("show", call).await
+        })
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(backend = "dbus", ?handle, summary = msg.summary)
+    )]
+    fn update(&self, handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            let mut handle = match handle {
+                Handle::DBus(handle) => handle,
+                Handle::Detached => return self.show(msg).await,
+            };
+
+            let notification = Self::build(msg);
+            let call = tokio::task::spawn_blocking(move || {
+                **handle = notification;
+                handle.update();
+                Handle::DBus(handle)
+            })
+            .map(|res| res.map_err(|err| NotificationFailureKind::Unknown(err.to_string())));
+
+            self.with_timeout("update", call).await
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "dbus", ?handle))]
+    fn close(&self, handle: Handle) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Handle::DBus(handle) = handle {
+                let _ = tokio::task::spawn_blocking(move || (*handle).close())
+                    .await
+                    .inspect_err(|err| tracing::error!("Failed to close notification: {err}"));
+            }
+        })
+    }
+}
+
+/// SubprocessNotifier sends notifications by invoking `notify-send`.
+///
+/// Doesn't require DBus client but can't update or close notifications.
+/// Updates are shown as new notifications.
+#[cfg(feature = "libnotify")]
+#[derive(Debug)]
+pub struct SubprocessNotifier {
+    cmd: String,
+}
+
+#[cfg(feature = "libnotify")]
+impl SubprocessNotifier {
+    pub fn new(cmd: String) -> Self {
+        Self { cmd }
+    }
+}
+
+#[cfg(feature = "libnotify")]
+impl Notifier for SubprocessNotifier {
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(backend = "subprocess", summary = msg.summary)
+    )]
+    fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            let mut cmd = tokio::process::Command::new(&self.cmd);
+            cmd.arg("--app-name")
+                .arg(env!("CARGO_PKG_NAME"))
+                .arg("--urgency=normal")
+                .arg("--expire-time")
+                .arg(msg.timeout.as_millis().to_string())
+                .arg("--icon")
+                .arg(&msg.icon);
+
+            if let Some(v) = msg.value {
+                cmd.arg("--hint").arg(format!("int:value:{v}"));
+            }
+
+            if msg.transient {
+                cmd.arg("--transient");
+            }
+
+            cmd.arg(&msg.summary);
+            if let Some(body) = &msg.body {
+                cmd.arg(body);
+            }
+
+            match cmd.status().await {
+                Ok(status) if status.success() => Ok(Handle::Detached),
+                Ok(status) => Err(NotificationFailureKind::Unknown(format!(
+                    "{} exited with {status}",
+                    self.cmd
+                ))),
+                Err(err) => Err(NotificationFailureKind::Unknown(format!(
+                    "failed to run {}: {err}",
+                    self.cmd
+                ))),
+            }
+        })
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(backend = "subprocess", summary = msg.summary)
+    )]
+    fn update(&self, _handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move { self.show(msg).await })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "subprocess"))]
+    fn close(&self, _handle: Handle) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// SocketNotifier writes notifications as JSON lines to a Unix socket.
+///
+/// Each notification is sent over a new connection.
+/// Notifications can't be updated or closed, listener is expected to replace
+/// previously displayed message.
+#[derive(Debug)]
+pub struct SocketNotifier {
+    socket: PathBuf,
+}
+
+impl SocketNotifier {
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    async fn send(&self, msg: Message) -> std::io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut line = serde_json::json!({
+            "summary": msg.summary,
+            "body": msg.body,
+            "icon": msg.icon,
+            "value": msg.value,
+            "timeout_ms": msg.timeout.as_millis() as u64,
+            "transient": msg.transient,
+        })
+        .to_string();
+        line.push('\n');
+
+        let mut conn = tokio::net::UnixStream::connect(&self.socket).await?;
+        conn.write_all(line.as_bytes()).await?;
+        conn.shutdown().await
+    }
+}
+
+impl Notifier for SocketNotifier {
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "socket", summary = msg.summary))]
+    fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            self.send(msg)
+                .await
+                .map(|_| Handle::Detached)
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+                        NotificationFailureKind::DaemonUnavailable
+                    }
+                    _ => NotificationFailureKind::Unknown(format!(
+                        "failed to write to {}: {err}",
+                        self.socket.display()
+                    )),
+                })
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "socket", summary = msg.summary))]
+    fn update(&self, _handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move { self.show(msg).await })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "socket"))]
+    fn close(&self, _handle: Handle) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// DryRunNotifier logs notifications instead of displaying them.
+#[derive(Debug, Default)]
+pub struct DryRunNotifier;
+
+impl DryRunNotifier {
+    fn log(op: &str, msg: &Message) {
+        tracing::info!(
+            summary = msg.summary,
+            body = msg.body,
+            icon = msg.icon,
+            value = msg.value,
+            timeout_ms = msg.timeout.as_millis() as u64,
+            transient = msg.transient,
+            "dry run: {op} notification"
+        );
+    }
+}
+
+impl Notifier for DryRunNotifier {
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "dry_run"))]
+    fn show(&self, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            Self::log("show", &msg);
+            Ok(Handle::Detached)
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "dry_run"))]
+    fn update(&self, _handle: Handle, msg: Message) -> BoxFuture<'_, NotifyResult> {
+        Box::pin(async move {
+            Self::log("update", &msg);
+            Ok(Handle::Detached)
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "dry_run"))]
+    fn close(&self, handle: Handle) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            tracing::info!(?handle, "dry run: close notification");
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use tracing::{debug, warn};
+
+const PID_FILE_NAME: &str = "reactord.pid";
+
+/// PidFile is a lock file containing the daemon's PID.
+///
+/// File is removed when value is dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Returns default PID file location - `$XDG_RUNTIME_DIR/reactord.pid`.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_RUNTIME_DIR").map(|dir| Path::new(&dir).join(PID_FILE_NAME))
+    }
+
+    /// Returns PID file location of an instance started with a config file,
+    /// e.g. `$XDG_RUNTIME_DIR/reactord-home-user-work.toml.pid` for `/home/user/work.toml`.
+    ///
+    /// Config path is canonicalized, so instances with the same config share a PID file.
+    pub fn path_for_config(config: &Path) -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+        let config = fs::canonicalize(config).unwrap_or_else(|_| config.to_path_buf());
+        Some(Path::new(&dir).join(file_name_for_config(&config)))
+    }
+
+    /// Creates a PID file at specified path.
+    ///
+    /// Returns an error if file belongs to another running reactord instance.
+    /// Stale PID files are overwritten.
+    pub fn create(path: PathBuf) -> Result<Self> {
+        if let Some(pid) = read_pid(&path) {
+            if is_running_instance(pid) {
+                bail!(
+                    "another instance is already running (pid {pid}, see {})",
+                    path.display()
+                );
+            }
+
+            warn!(pid, path = %path.display(), "overwriting stale pid file");
+        }
+
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("failed to write pid file {}", path.display()))?;
+
+        debug!(path = %path.display(), "created pid file");
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), "failed to remove pid file: {err}");
+        }
+    }
+}
+
+fn file_name_for_config(config: &Path) -> String {
+    let name = config
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .replace('/', "-");
+    format!("reactord-{name}.pid")
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Checks whether process with specified PID is alive and runs the same executable.
+fn is_running_instance(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return false;
+    }
+
+    let exe = match fs::read_link(format!("/proc/{pid}/exe")) {
+        Ok(exe) => exe,
+        Err(_) => return false,
+    };
+
+    std::env::current_exe()
+        .map(|current| current == exe)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_is_derived_from_config_path() {
+        assert_eq!(
+            file_name_for_config(Path::new("/home/user/.config/reactord/work.toml")),
+            "reactord-home-user-.config-reactord-work.toml.pid"
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Actions sent from the PipeWire thread to the main loop.
+
+use crate::{
+    state::{ActionType, TimestampedAction, VolumeInfo},
+    utils,
+};
+
+pub type ActionSender = tokio::sync::mpsc::Sender<TimestampedAction<ActionType>>;
+pub type ActionListener = tokio::sync::mpsc::Receiver<TimestampedAction<ActionType>>;
+
+/// Builds volume change action with a difference from previously reported volume.
+pub fn volume_change_action(
+    oid: u32,
+    vol: VolumeInfo,
+    track_volume: &utils::VolumeTracker,
+) -> ActionType {
+    match track_volume(oid, &vol) {
+        Some(delta) => ActionType::VolumeChangeWithDelta(oid, vol, delta),
+        None => ActionType::VolumeChangeRaw(oid, vol),
+    }
+}
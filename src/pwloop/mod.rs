@@ -0,0 +1,607 @@
+//! PipeWire event listener.
+//!
+//! PipeWire objects aren't thread-safe, so listener runs on a dedicated thread
+//! and reports changes to the main loop as [ActionType] events.
+
+mod events;
+mod subscribe;
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::{
+    config::ListenerOptions,
+    state::{ActionType, DeviceKindSet, Entry, GlobalChangeStats, PwErrorKind, VolumeInfo},
+    utils,
+    volumestore::VolumeStore,
+};
+use anyhow::{Context, Result, anyhow};
+use pipewire as pw;
+use tokio::sync::oneshot;
+use tracing::{debug, debug_span, error, info, warn};
+use utils::{NameTransform, PWContext};
+
+pub use events::ActionListener;
+use events::ActionSender;
+use subscribe::on_global_change;
+
+/// `application.id` of WirePlumber's internal policy endpoints.
+const WIREPLUMBER_POLICY_APP_ID: &str = "org.freedesktop.pipewire.policy-endpoint";
+
+pub struct ListenerConfig {
+    message_buffer_size: usize,
+
+    /// Patterns of entry names or labels to ignore.
+    ignore_list: Vec<glob::Pattern>,
+
+    /// Names or labels of entries to track. Ignore list is not used if set.
+    allow_list: Option<std::collections::HashSet<String>>,
+    name_transform: Option<Box<NameTransform>>,
+
+    /// Ignore WirePlumber's internal nodes.
+    suppress_wireplumber_nodes: bool,
+
+    /// Max time volume change event can wait in the queue before being dropped.
+    max_message_age_ms: u64,
+
+    /// Device kinds to track. All kinds are tracked if not set.
+    device_kind_filter: Option<DeviceKindSet>,
+
+    /// Custom display names by entry name or label.
+    aliases: std::collections::HashMap<String, String>,
+
+    /// Fetch params of new nodes and devices right after subscription.
+    ///
+    /// PipeWire sends current params on subscription, which produces a burst of events
+    /// at startup on systems with many devices. If disabled, params are subscribed
+    /// on the first params change instead, so volume is unknown until it changes
+    /// and the first change isn't reported as a delta.
+    subscribe_initial_params: bool,
+
+    /// Volumes to restore on new nodes after initial params fetch.
+    ///
+    /// Volumes aren't restored if not set.
+    volume_store: Option<VolumeStore>,
+
+    /// Max time to wait for PipeWire to report existing objects at startup.
+    ///
+    /// Listener reports a fatal error if initial sync doesn't complete in time.
+    initial_sync_timeout: Duration,
+
+    /// Reconnect to PipeWire when connection is lost or can't be established.
+    ///
+    /// If disabled, listener stops instead.
+    reconnect: bool,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            message_buffer_size: 5,
+            ignore_list: Default::default(),
+            allow_list: None,
+            name_transform: None,
+            suppress_wireplumber_nodes: true,
+            max_message_age_ms: 500,
+            device_kind_filter: None,
+            aliases: Default::default(),
+            subscribe_initial_params: true,
+            volume_store: None,
+            initial_sync_timeout: Duration::from_secs(10),
+            reconnect: true,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+#[allow(dead_code)]
+impl ListenerConfig {
+    /// Returns config for integration tests.
+    ///
+    /// Uses a large buffer to not drop events, tracks all entries
+    /// and fails fast if PipeWire isn't responding.
+    pub fn for_testing() -> Self {
+        Self {
+            message_buffer_size: 100,
+            initial_sync_timeout: Duration::from_secs(1),
+            reconnect: false,
+            ..Self::minimal()
+        }
+    }
+
+    /// Returns config without any filters, transforms or persisted state.
+    pub fn minimal() -> Self {
+        Self {
+            message_buffer_size: 1,
+            ignore_list: Vec::new(),
+            allow_list: None,
+            name_transform: None,
+            suppress_wireplumber_nodes: false,
+            max_message_age_ms: 500,
+            device_kind_filter: None,
+            aliases: Default::default(),
+            subscribe_initial_params: true,
+            volume_store: None,
+            initial_sync_timeout: Duration::from_secs(10),
+            reconnect: true,
+        }
+    }
+}
+
+impl ListenerConfig {
+    /// Sets a function to convert raw PipeWire object names into entry labels.
+    #[allow(dead_code)]
+    pub fn with_name_transform(
+        mut self,
+        f: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.name_transform = Some(Box::new(f));
+        self
+    }
+
+    pub fn set_message_buffer_size(&mut self, s: usize) {
+        self.message_buffer_size = s;
+    }
+
+    /// Sets glob patterns of entry names or labels to ignore, e.g. `easyeffects_*`.
+    ///
+    /// Returns an error if any pattern is invalid.
+    pub fn set_ignore_list(&mut self, ignore_list: Vec<String>) -> Result<()> {
+        self.ignore_list = ignore_list
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p).with_context(|| format!("invalid ignore list pattern {p:?}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Sets names or labels of entries to track, all other entries are ignored.
+    ///
+    /// Ignore list is not used while allow list is set.
+    pub fn set_allow_list(&mut self, allow_list: Option<std::collections::HashSet<String>>) {
+        self.allow_list = allow_list;
+    }
+
+    pub fn set_suppress_wireplumber_nodes(&mut self, v: bool) {
+        self.suppress_wireplumber_nodes = v;
+    }
+
+    pub fn set_max_message_age_ms(&mut self, v: u64) {
+        self.max_message_age_ms = v;
+    }
+
+    pub fn max_message_age(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_message_age_ms)
+    }
+
+    pub fn set_device_kind_filter(&mut self, kinds: Option<DeviceKindSet>) {
+        self.device_kind_filter = kinds;
+    }
+
+    /// Sets custom display names by entry name or label.
+    pub fn set_aliases(&mut self, aliases: std::collections::HashMap<String, String>) {
+        self.aliases = aliases;
+    }
+
+    pub fn set_subscribe_initial_params(&mut self, v: bool) {
+        self.subscribe_initial_params = v;
+    }
+
+    /// Sets volumes to restore on new nodes.
+    pub fn set_volume_store(&mut self, store: Option<VolumeStore>) {
+        self.volume_store = store;
+    }
+
+    pub fn set_initial_sync_timeout(&mut self, timeout: Duration) {
+        self.initial_sync_timeout = timeout;
+    }
+
+    pub fn set_reconnect(&mut self, v: bool) {
+        self.reconnect = v;
+    }
+
+    /// Overrides settings with values set in config file.
+    pub fn apply_config(&mut self, opts: &ListenerOptions) -> Result<()> {
+        if let Some(ignore_list) = &opts.ignore_list {
+            self.set_ignore_list(ignore_list.clone())?;
+        }
+
+        if let Some(allow_list) = &opts.allow_list {
+            self.set_allow_list(Some(allow_list.clone()));
+        }
+
+        if let Some(aliases) = &opts.aliases {
+            self.set_aliases(aliases.clone());
+        }
+
+        if let Some(kinds) = &opts.watch_kinds {
+            self.set_device_kind_filter(Some(kinds.iter().map(DeviceKindSet::from).collect()));
+        }
+
+        if let Some(v) = opts.suppress_wireplumber_nodes {
+            self.set_suppress_wireplumber_nodes(v);
+        }
+
+        if let Some(v) = opts.max_message_age_ms {
+            self.set_max_message_age_ms(v);
+        }
+
+        if let Some(v) = opts.message_buffer_size {
+            self.set_message_buffer_size(v);
+        }
+
+        if let Some(v) = opts.subscribe_initial_params {
+            self.set_subscribe_initial_params(v);
+        }
+
+        if let Some(v) = opts.initial_sync_timeout_ms {
+            self.set_initial_sync_timeout(Duration::from_millis(v));
+        }
+
+        Ok(())
+    }
+
+    /// Applies configured name transform and aliases to a freshly parsed entry.
+    pub fn apply_entry_options(&self, e: &mut Entry) {
+        if let Some(transform) = self.name_transform.as_deref() {
+            e.apply_name_transform(transform);
+        }
+
+        if !self.aliases.is_empty() {
+            e.apply_aliases(&self.aliases);
+        }
+    }
+
+    /// Returns saved volume of an entry to restore.
+    pub fn saved_volume(&self, e: &Entry) -> Option<VolumeInfo> {
+        self.volume_store.as_ref()?.load(e.name.as_deref()?)
+    }
+
+    /// Returns whether entry should not be tracked.
+    pub fn is_entry_ignored(&self, e: &Entry) -> bool {
+        if let Some(kinds) = self.device_kind_filter
+            && !kinds.contains_kind(&e.kind)
+        {
+            return true;
+        }
+
+        if self.suppress_wireplumber_nodes && e.app_id.as_deref() == Some(WIREPLUMBER_POLICY_APP_ID)
+        {
+            return true;
+        }
+
+        let mut names = [e.name.as_deref(), e.label.as_deref()]
+            .into_iter()
+            .flatten();
+        match &self.allow_list {
+            Some(allow_list) => !names.any(|v| allow_list.contains(v)),
+            None => names.any(|v| self.ignore_list.iter().any(|p| p.matches(v))),
+        }
+    }
+}
+
+/// First delay before reconnecting to PipeWire. Doubled after each failed attempt.
+const RECONNECT_DELAY_MIN: Duration = Duration::from_millis(500);
+
+/// Max delay between attempts to reconnect to PipeWire.
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// How often to check cancellation signal while waiting for other events.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// SessionEnd describes why connection to PipeWire was closed.
+enum SessionEnd {
+    Cancelled,
+
+    /// PipeWire closed the connection, e.g. when daemon was restarted.
+    Disconnected,
+}
+
+/// Starts a separate thread to listen for PipeWire events.
+/// Thread is terminated as soon as a new message received from a cancellation token channel.
+///
+/// If connection to PipeWire is lost, listener reports [ActionType::Disconnected]
+/// and reconnects with exponential back-off, unless reconnection is disabled.
+///
+/// Returns event channel to listen for incoming events.
+pub fn start_pw_thread(
+    mut cancel_token: oneshot::Receiver<()>,
+    cfg: ListenerConfig,
+) -> Result<ActionListener> {
+    let (tx, rx) = tokio::sync::mpsc::channel(cfg.message_buffer_size);
+
+    let _h = std::thread::spawn(move || {
+        let span = tracing::info_span!("pw");
+        let _h = span.enter();
+
+        debug!("initializing pipewire");
+        pw::init();
+        debug!("initialized");
+
+        let cfg = Rc::new(cfg);
+        let mut reconnect_delay = RECONNECT_DELAY_MIN;
+        loop {
+            match run_session(&cfg, &tx, &mut cancel_token) {
+                Ok(SessionEnd::Cancelled) => break,
+                Ok(SessionEnd::Disconnected) => {
+                    warn!("lost connection to pipewire");
+                    reconnect_delay = RECONNECT_DELAY_MIN;
+
+                    // Objects are enumerated again after reconnect.
+                    if tx.blocking_send(ActionType::Disconnected.into()).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => error!("pipewire session failed: {err:#}"),
+            }
+
+            if !cfg.reconnect {
+                break;
+            }
+
+            info!(
+                delay_ms = reconnect_delay.as_millis() as u64,
+                "reconnecting to pipewire"
+            );
+            if wait_for_cancel(&mut cancel_token, reconnect_delay) {
+                break;
+            }
+
+            reconnect_delay = (reconnect_delay * 2).min(RECONNECT_DELAY_MAX);
+        }
+
+        let _ = tx.blocking_send(ActionType::Shutdown.into());
+    });
+
+    Ok(rx)
+}
+
+/// Waits for a cancellation signal for up to `timeout`.
+///
+/// Returns whether cancellation was requested.
+fn wait_for_cancel(cancel_token: &mut oneshot::Receiver<()>, timeout: Duration) -> bool {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if !matches!(
+            cancel_token.try_recv(),
+            Err(oneshot::error::TryRecvError::Empty)
+        ) {
+            return true;
+        }
+
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            return false;
+        }
+
+        std::thread::sleep(left.min(CANCEL_POLL_INTERVAL));
+    }
+}
+
+/// Connects to PipeWire and reports events until cancellation or disconnect.
+fn run_session(
+    cfg: &Rc<ListenerConfig>,
+    tx: &ActionSender,
+    cancel_token: &mut oneshot::Receiver<()>,
+) -> Result<SessionEnd> {
+    let pwctx =
+        PWContext::new_shared(Some(c"reactord-pw")).context("failed to build pipewire consumer")?;
+
+    // refcounters to be passed to the callback.
+    let cctx = pwctx.clone();
+    let rm_ctx = pwctx.clone();
+    let sent_tx = tx.clone();
+    let rm_tx = tx.clone();
+
+    let initial_sync_timeout = cfg.initial_sync_timeout;
+    let reconnect = cfg.reconnect;
+    let cfg_rc = cfg.clone();
+    let stats = Rc::new(RefCell::new(GlobalChangeStats::default()));
+    let global_stats = stats.clone();
+    debug!("registering listener...");
+    let _listener = pwctx
+        .registry
+        .add_listener_local()
+        .global(move |global| {
+            if let Err(err) = on_global_change(
+                cctx.clone(),
+                cfg_rc.clone(),
+                &global_stats,
+                sent_tx.clone(),
+                global,
+            ) {
+                global_stats.borrow_mut().errors += 1;
+                error!("on global change hook returned an error: {err}");
+                let kind = PwErrorKind {
+                    msg: format!("{err:#}"),
+                    is_fatal: false,
+                };
+                let _ = sent_tx.blocking_send(ActionType::Error(global.id, kind).into());
+            }
+        })
+        .global_remove(move |id| {
+            let span = debug_span!("global_remove", obj_id = id);
+            let _g = span.enter();
+
+            debug!("global removed");
+            rm_ctx.forget_global(id);
+            let action = if rm_ctx.forget_link(id) {
+                ActionType::LinkRemove(id)
+            } else if rm_ctx.forget_client(id) {
+                ActionType::ClientRemove(id)
+            } else {
+                ActionType::GlobalRemove(id)
+            };
+
+            if let Err(err) = rm_tx.blocking_send(action.into()) {
+                error!(obj_id = id, "failed to dispatch removal: {err}");
+            }
+        })
+        .register();
+
+    // Registry sends all existing objects before replying to the first sync.
+    let startup_seq = pwctx
+        .core
+        .sync(0)
+        .inspect_err(|err| error!("failed to sync with pipewire core: {err}"))
+        .ok();
+    let stats_tx = tx.clone();
+    let err_tx = tx.clone();
+    let info_tx = tx.clone();
+    let version_sent = Cell::new(false);
+    let (sync_done_tx, sync_done_rx) = mpsc::channel::<()>();
+    let (disconnect_tx, disconnect_rx) = mpsc::channel::<()>();
+    let _core_listener = pwctx
+        .core
+        .add_listener_local()
+        .info(move |info| {
+            // Info is sent again on props change, version stays the same.
+            if version_sent.replace(true) {
+                return;
+            }
+
+            let version = info.version().to_string();
+            debug!(version, "connected to pipewire");
+            let action = ActionType::PipeWireVersion(version);
+            if let Err(err) = info_tx.blocking_send(action.into()) {
+                error!("failed to dispatch PipeWireVersion: {err}");
+            }
+        })
+        .error(move |id, seq, res, message| {
+            // Errors on core object mean that connection is broken.
+            let is_fatal = id == pw::core::PW_ID_CORE;
+            error!(obj_id = id, seq, res, is_fatal, "pipewire error: {message}");
+            if is_fatal && reconnect {
+                let _ = disconnect_tx.send(());
+                return;
+            }
+
+            let kind = PwErrorKind {
+                msg: message.to_string(),
+                is_fatal,
+            };
+            let _ = err_tx.blocking_send(ActionType::Error(id, kind).into());
+        })
+        .done(move |id, seq| {
+            if id != pw::core::PW_ID_CORE || Some(seq) != startup_seq {
+                return;
+            }
+
+            let _ = sync_done_tx.send(());
+            let stats = stats.borrow().clone();
+            if let Err(err) = stats_tx.blocking_send(ActionType::StartupStats(stats).into()) {
+                error!("failed to dispatch StartupStats: {err}");
+            }
+        })
+        .register();
+
+    let mut result = Ok(SessionEnd::Cancelled);
+    debug!("starting thread loop...");
+    pwctx.begin(|| {
+        // Cancellation isn't checked until initial sync is done or timed out.
+        if startup_seq.is_some()
+            && let Err(mpsc::RecvTimeoutError::Timeout) =
+                sync_done_rx.recv_timeout(initial_sync_timeout)
+        {
+            error!(
+                timeout_ms = initial_sync_timeout.as_millis() as u64,
+                "pipewire didn't complete initial sync in time"
+            );
+
+            // Session is retried if reconnection is enabled, repeated timeouts are limited
+            // by the error count threshold of the main loop.
+            let kind = PwErrorKind {
+                msg: "initial sync timeout".to_string(),
+                is_fatal: !reconnect,
+            };
+            let _ = tx.blocking_send(ActionType::Error(0, kind).into());
+            if reconnect {
+                result = Err(anyhow!("initial sync timeout"));
+                return;
+            }
+        }
+
+        // Suspend thread until cancellation signal is sent or connection is lost.
+        // PW's ThreadLoop already manages its own thread under the hood.
+        loop {
+            if disconnect_rx.recv_timeout(CANCEL_POLL_INTERVAL).is_ok() {
+                result = Ok(SessionEnd::Disconnected);
+                return;
+            }
+
+            if !matches!(
+                cancel_token.try_recv(),
+                Err(oneshot::error::TryRecvError::Empty)
+            ) {
+                break;
+            }
+        }
+
+        info!("shutting down...");
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DeviceKind;
+
+    fn sink(name: &str, label: &str) -> Entry {
+        Entry {
+            id: 42,
+            is_node: true,
+            name: Some(name.to_string()),
+            label: Some(label.to_string()),
+            kind: DeviceKind::Sink,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ignore_list_matches_name_or_label() {
+        let mut cfg = ListenerConfig::minimal();
+        assert!(!cfg.is_entry_ignored(&sink("easyeffects_sink", "Easy Effects Sink")));
+
+        cfg.set_ignore_list(vec!["easyeffects_*".to_string(), "HDMI *".to_string()])
+            .unwrap();
+        assert!(cfg.is_entry_ignored(&sink("easyeffects_sink", "Easy Effects Sink")));
+        assert!(cfg.is_entry_ignored(&sink("alsa_output.hdmi", "HDMI Output")));
+        assert!(!cfg.is_entry_ignored(&sink("alsa_output.pci", "Speakers")));
+    }
+
+    #[test]
+    fn allow_list_ignores_other_entries() {
+        let mut cfg = ListenerConfig::minimal();
+        cfg.set_ignore_list(vec!["*".to_string()]).unwrap();
+        cfg.set_allow_list(Some(["Speakers".to_string()].into()));
+
+        // Ignore list isn't used along with allow list.
+        assert!(!cfg.is_entry_ignored(&sink("alsa_output.pci", "Speakers")));
+        assert!(cfg.is_entry_ignored(&sink("alsa_output.hdmi", "HDMI Output")));
+    }
+
+    #[test]
+    fn device_kind_filter_ignores_other_kinds() {
+        let mut cfg = ListenerConfig::minimal();
+        cfg.set_device_kind_filter(Some(DeviceKindSet::OUTPUTS));
+
+        let mut e = sink("alsa_input.pci", "Microphone");
+        assert!(!cfg.is_entry_ignored(&e));
+
+        e.kind = DeviceKind::Source;
+        assert!(cfg.is_entry_ignored(&e));
+    }
+
+    #[test]
+    fn invalid_ignore_pattern_is_rejected() {
+        let mut cfg = ListenerConfig::minimal();
+        assert!(cfg.set_ignore_list(vec!["[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//! Subscriptions to PipeWire registry objects.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use super::{
+    ListenerConfig,
+    events::{ActionSender, volume_change_action},
+};
+use crate::{
+    state::{ActionType, DefaultKind, Entry, GlobalChangeStats, ThresholdConfig, VolumeInfo},
+    utils,
+};
+use anyhow::{Context, Result};
+use pipewire::{
+    self as pw,
+    proxy::ProxyT,
+    spa::{param::ParamType, utils::dict::DictRef},
+};
+use pw::types::ObjectType;
+use tracing::{debug, debug_span, error};
+use utils::{PWContextRc, PWGlobalObject};
+
+/// Device params to subscribe to.
+const DEVICE_PARAMS: &[ParamType] = &[ParamType::Props, ParamType::Route];
+
+/// Node params to subscribe to.
+const NODE_PARAMS: &[ParamType] = &[ParamType::Props, ParamType::Route, ParamType::Format];
+
+/// Returns info event callback which subscribes to object params on the first params change.
+///
+/// Used when initial params fetch is disabled. The first info event is sent right after bind
+/// and is skipped.
+fn deferred_params_subscription(
+    oid: u32,
+    ids: &'static [ParamType],
+    subscribe_params: utils::ParamsSubscriber,
+) -> impl Fn(bool) + 'static {
+    let info_seen = Cell::new(false);
+    let subscribed = Cell::new(false);
+    move |params_changed| {
+        if !info_seen.replace(true) || !params_changed || subscribed.get() {
+            return;
+        }
+
+        debug!(oid, "params changed, subscribing");
+        subscribed.set(subscribe_params(oid, ids));
+    }
+}
+
+/// Returns info event callback which dispatches [ActionType::EntryUpdate] on props change.
+///
+/// Callback accepts global ID of an object and props if they changed. The first info event
+/// is sent right after bind and carries the same props as the registry global, so it's skipped.
+fn props_update_dispatcher(
+    oid: u32,
+    type_: ObjectType,
+    cfg: Rc<ListenerConfig>,
+    sender: ActionSender,
+) -> impl Fn(u32, Option<&DictRef>) + 'static {
+    let info_seen = Cell::new(false);
+    move |global_id: u32, props: Option<&DictRef>| {
+        if !info_seen.replace(true) {
+            return;
+        }
+
+        let Some(mut entry) = props.and_then(|p| Entry::from_props(global_id, &type_, p)) else {
+            return;
+        };
+
+        cfg.apply_entry_options(&mut entry);
+        debug!(oid, "entry props updated");
+        if let Err(err) = sender.blocking_send(ActionType::EntryUpdate(oid, entry).into()) {
+            error!(oid, "failed to dispatch EntryUpdate: {err}");
+        }
+    }
+}
+
+/// Returns volume change callback which restores saved volume on the first volume report.
+///
+/// Volume is set only if it differs from the reported one.
+fn saved_volume_restorer(
+    oid: u32,
+    saved: Option<VolumeInfo>,
+    set_volume: utils::VolumeSetter,
+) -> impl Fn(&ActionType) + 'static {
+    let saved = Cell::new(saved);
+    move |action| {
+        let ActionType::VolumeChangeRaw(_, vol) = action else {
+            return;
+        };
+
+        let Some(saved) = saved.take() else {
+            return;
+        };
+
+        if !saved.is_significant_change_from(vol, &ThresholdConfig::default()) {
+            return;
+        }
+
+        debug!(oid, current = ?vol, ?saved, "restoring saved volume");
+        if let Err(err) = set_volume(oid, &saved) {
+            error!(oid, "failed to restore saved volume: {err:#}");
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "subscribe_device",
+    skip(ctx, cfg, sender, dev),
+    fields(device_id = dev.upcast_ref().id()),
+)]
+fn subscribe_device(
+    ctx: PWContextRc,
+    cfg: Rc<ListenerConfig>,
+    sender: ActionSender,
+    dev: pw::device::Device,
+) {
+    let subscribe_initial_params = cfg.subscribe_initial_params;
+    if subscribe_initial_params {
+        dev.subscribe_params(DEVICE_PARAMS);
+    }
+
+    let track_volume = ctx.volume_tracker();
+    let subscribe_params = ctx.params_subscriber();
+    let dev_id = ctx.device_listener_local(dev, move |dev_id, b| {
+        let vol_sender = sender.clone();
+        let track_volume = track_volume.clone();
+        let on_params_info = (!subscribe_initial_params)
+            .then(|| deferred_params_subscription(dev_id, DEVICE_PARAMS, subscribe_params.clone()));
+        let on_props_info =
+            props_update_dispatcher(dev_id, ObjectType::Device, cfg.clone(), sender.clone());
+
+        b.info(move |info| {
+            let mask = info.change_mask();
+            if let Some(on_info) = &on_params_info {
+                on_info(mask.contains(pw::device::DeviceChangeMask::PARAMS));
+            }
+
+            let props = info
+                .props()
+                .filter(|_| mask.contains(pw::device::DeviceChangeMask::PROPS));
+            on_props_info(info.id(), props);
+        })
+        .param(move |_seq, param_type, _idx, _next, param| {
+            let span = debug_span!("device_listener", dev_id);
+            let _g = span.enter();
+
+            if param_type != ParamType::Props {
+                return;
+            }
+
+            // TODO: support other prop change events?
+            if let Some(mut vol) = param.and_then(utils::volume_from_pod) {
+                vol.normalize_channel_volumes(dev_id);
+                debug!(%dev_id, volume = ?vol, "device volume change");
+                let action = volume_change_action(dev_id, vol, &track_volume);
+                let _ = vol_sender.blocking_send(action.into());
+            }
+        })
+    });
+
+    let on_removed = Box::new(|oid: u32| {
+        let span = debug_span!("device_removed", dev_id = oid);
+        let _g = span.enter();
+        debug!("device removed");
+    });
+    if let Err(err) = ctx.removed_listener(dev_id, on_removed) {
+        error!(dev_id, "failed to add device removal listener: {err:#}");
+    }
+}
+
+#[tracing::instrument(
+    name = "subscribe_node",
+    skip(ctx, cfg, sender, node),
+    fields(node_id = node.upcast_ref().id()),
+)]
+fn subscribe_node(
+    ctx: PWContextRc,
+    cfg: Rc<ListenerConfig>,
+    sender: ActionSender,
+    node: pw::node::Node,
+    saved_volume: Option<VolumeInfo>,
+) {
+    let subscribe_initial_params = cfg.subscribe_initial_params;
+    if subscribe_initial_params {
+        node.subscribe_params(NODE_PARAMS);
+    }
+
+    let track_volume = ctx.volume_tracker();
+    let subscribe_params = ctx.params_subscriber();
+    let set_volume = ctx.volume_setter();
+    let node_id = ctx.node_listener_local(node, move |node_id, b| {
+        let vol_sender = sender.clone();
+        let restore_volume =
+            saved_volume_restorer(node_id, saved_volume.clone(), set_volume.clone());
+        let track_volume = track_volume.clone();
+        let on_params_info = (!subscribe_initial_params)
+            .then(|| deferred_params_subscription(node_id, NODE_PARAMS, subscribe_params.clone()));
+        let on_props_info =
+            props_update_dispatcher(node_id, ObjectType::Node, cfg.clone(), sender.clone());
+
+        b.info(move |info| {
+            let mask = info.change_mask();
+            if let Some(on_info) = &on_params_info {
+                on_info(mask.contains(pw::node::NodeChangeMask::PARAMS));
+            }
+
+            let props = info
+                .props()
+                .filter(|_| mask.contains(pw::node::NodeChangeMask::PROPS));
+            on_props_info(info.id(), props);
+        })
+        .param(move |_seq, param_type, _idx, _next, param| {
+            let span = debug_span!("node_listener", node_id);
+            let _g = span.enter();
+
+            match param_type {
+                ParamType::Props => {
+                    if let Some(mut vol) = param.and_then(utils::volume_from_pod) {
+                        vol.normalize_channel_volumes(node_id);
+                        debug!(%node_id, volume = ?vol, "node volume change");
+                        let action = volume_change_action(node_id, vol, &track_volume);
+                        restore_volume(&action);
+                        let _ = vol_sender.blocking_send(action.into());
+                    }
+                }
+                ParamType::Format => {
+                    if let Some(fmt) = param.and_then(utils::audio_format_from_pod) {
+                        debug!(%node_id, format = ?fmt, "node format change");
+                        let _ = vol_sender
+                            .blocking_send(ActionType::FormatChanged(node_id, fmt).into());
+                    }
+                }
+                _ => {
+                    debug!(?param_type, "skip unsupported node param type");
+                }
+            }
+        })
+    });
+
+    let on_removed = Box::new(|oid: u32| {
+        let span = debug_span!("node_removed", node_id = oid);
+        let _g = span.enter();
+        debug!("node removed");
+    });
+    if let Err(err) = ctx.removed_listener(node_id, on_removed) {
+        error!(node_id, "failed to add node removal listener: {err:#}");
+    }
+}
+
+/// Names of metadata objects to watch.
+///
+/// `default` holds default sink and source, `settings` holds clock rate and quantum.
+const WATCHED_METADATA: &[&str] = &["default", "settings"];
+
+fn subscribe_metadata(ctx: PWContextRc, sender: ActionSender, o: &PWGlobalObject) -> Result<()> {
+    let name = match o.props.and_then(|p| p.get("metadata.name")) {
+        Some(name) if WATCHED_METADATA.contains(&name) => name.to_string(),
+        _ => return Ok(()),
+    };
+
+    if ctx.find_object(o.id).is_some() {
+        return Ok(());
+    }
+
+    let metadata: pw::metadata::Metadata = ctx
+        .registry
+        .bind(o)
+        .with_context(|| format!("failed to bind metadata {name}"))?;
+
+    debug!(name = &name, "new metadata");
+    let oid = ctx.register_metadata_listener(metadata, None, move |subject, key, value| {
+        debug!(
+            metadata = &name,
+            subject, key, value, "metadata property changed"
+        );
+
+        if name == "settings" {
+            if key != "clock.quantum" {
+                return;
+            }
+
+            let Ok(quantum) = value.parse::<u32>() else {
+                error!(key, value, "failed to parse clock quantum metadata value");
+                return;
+            };
+
+            if let Err(err) = sender.blocking_send(ActionType::QuantumChanged(quantum).into()) {
+                error!(key, "failed to dispatch QuantumChanged: {err}");
+            }
+            return;
+        }
+
+        let Some(kind) = DefaultKind::from_metadata_key(key) else {
+            return;
+        };
+
+        let Some(device_name) = utils::parse_metadata_device_name(value) else {
+            error!(key, value, "failed to parse default device metadata value");
+            return;
+        };
+
+        let action = ActionType::DefaultChanged(kind, device_name);
+        if let Err(err) = sender.blocking_send(action.into()) {
+            error!(key, "failed to dispatch DefaultChanged: {err}");
+        }
+    });
+
+    ctx.register_global(o.id, oid);
+    Ok(())
+}
+
+fn add_client(ctx: PWContextRc, sender: ActionSender, o: &PWGlobalObject) {
+    let Some(client) = utils::parse_client(o) else {
+        return;
+    };
+
+    // Skip clients reported before.
+    if !ctx.track_client(client.id) {
+        return;
+    }
+
+    let client_id = client.id;
+    debug!(?client, "new client");
+    if let Err(err) = sender.blocking_send(ActionType::ClientAdd(client_id, client).into()) {
+        error!(client_id, "failed to dispatch ClientAdd: {err}");
+    }
+}
+
+fn subscribe_link(ctx: PWContextRc, sender: ActionSender, o: &PWGlobalObject) -> Result<()> {
+    if ctx.is_link_tracked(o.id) {
+        return Ok(());
+    }
+
+    let Some(link) = utils::parse_link(o) else {
+        return Ok(());
+    };
+
+    let link_id = link.id;
+    let proxy: pw::link::Link = ctx
+        .registry
+        .bind(o)
+        .with_context(|| format!("failed to bind link {link_id}"))?;
+
+    // Removal is reported only for tracked links, so unbound links aren't announced.
+    ctx.track_link(o.id);
+    debug!(?link, "new link");
+    if let Err(err) = sender.blocking_send(ActionType::LinkAdd(link).into()) {
+        error!(link_id, "failed to dispatch LinkAdd: {err}");
+    }
+
+    ctx.link_listener_local(proxy, move |_, b| {
+        let sender = sender.clone();
+        b.info(move |info| {
+            if !info.change_mask().contains(pw::link::LinkChangeMask::STATE) {
+                return;
+            }
+
+            let state = utils::link_state(info.state());
+            debug!(link_id, ?state, "link state change");
+            let _ = sender.blocking_send(ActionType::LinkStateChanged(link_id, state).into());
+        })
+    });
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "global_change",
+    skip(ctx, cfg, stats, sender, o),
+    fields(obj_id = o.id),
+)]
+pub fn on_global_change(
+    ctx: PWContextRc,
+    cfg: std::rc::Rc<ListenerConfig>,
+    stats: &RefCell<GlobalChangeStats>,
+    sender: ActionSender,
+    o: &PWGlobalObject,
+) -> Result<()> {
+    {
+        let mut stats = stats.borrow_mut();
+        stats.seen += 1;
+        *stats
+            .by_type
+            .entry(o.type_.to_str().to_string())
+            .or_default() += 1;
+    }
+
+    match o.type_ {
+        ObjectType::Metadata => return subscribe_metadata(ctx, sender, o),
+        ObjectType::Link => return subscribe_link(ctx, sender, o),
+        ObjectType::Client => {
+            add_client(ctx, sender, o);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut entry = match Entry::from_global_object(o) {
+        Some(e) => e,
+        None => {
+            return Ok(());
+        }
+    };
+
+    cfg.apply_entry_options(&mut entry);
+
+    stats.borrow_mut().parsed += 1;
+
+    // Ignored objects still can be bound by name on demand.
+    ctx.track_global(o);
+
+    let label = utils::format_object_label(o);
+    if cfg.is_entry_ignored(&entry) {
+        stats.borrow_mut().ignored += 1;
+        debug!(label = &label, "skip ignored entry");
+        return Ok(());
+    }
+
+    match o.type_ {
+        ObjectType::Node if utils::is_audio_node(&o.props) => {
+            let node: pw::node::Node = ctx
+                .registry
+                .bind(o)
+                .with_context(|| format!("failed to bind node #{}", &label))?;
+
+            let node_id = node.upcast_ref().id();
+            let saved_volume = cfg.saved_volume(&entry);
+            debug!(node_id, label = &label, "new node");
+            if let Err(err) = sender.blocking_send(ActionType::EntryAdd(node_id, entry).into()) {
+                error!(
+                    node_id,
+                    label = &label,
+                    "failed to dispatch EntryAdd: {err}"
+                );
+            }
+
+            ctx.register_global(o.id, node_id);
+            subscribe_node(ctx, cfg, sender, node, saved_volume);
+        }
+        ObjectType::Device if utils::is_audio_device(&o.props).is_some() => {
+            let dev: pw::device::Device = ctx.registry.bind(o).with_context(|| {
+                format!("failed to bind device {}", utils::format_object_label(o))
+            })?;
+
+            let dev_id = dev.upcast_ref().id();
+            debug!(dev_id, label = &label, "new device");
+            if let Err(err) = sender.blocking_send(ActionType::EntryAdd(dev_id, entry).into()) {
+                error!(dev_id, label = &label, "failed to dispatch EntryAdd: {err}");
+            }
+
+            ctx.register_global(o.id, dev_id);
+            subscribe_device(ctx, cfg, sender, dev);
+        }
+        _ => {}
+    };
+
+    Ok(())
+}
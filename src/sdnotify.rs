@@ -0,0 +1,30 @@
+//! systemd service manager notifications.
+//!
+//! All functions are no-op if daemon isn't started by systemd as a `Type=notify` service.
+
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tracing::warn;
+
+/// Reports that the daemon finished startup.
+pub fn notify_ready() {
+    if let Err(err) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        warn!("failed to notify systemd about readiness: {err}");
+    }
+}
+
+/// Returns how often to kick the watchdog if enabled with `WatchdogSec=`.
+///
+/// Watchdog is kicked twice per `WATCHDOG_USEC` as recommended by systemd.
+pub fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec) / 2)
+}
+
+/// Reports that the daemon is alive.
+pub fn kick_watchdog() {
+    if let Err(err) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+        warn!("failed to kick systemd watchdog: {err}");
+    }
+}
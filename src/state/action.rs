@@ -0,0 +1,231 @@
+//! Actions reported by PipeWire listener.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{AudioFormat, ClientEntry, Entry, LinkEntry, LinkState, VolumeInfo, VolumeInfoDelta};
+
+/// GlobalChangeStats is a summary of registry objects processed by PipeWire listener.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalChangeStats {
+    pub seen: u64,
+
+    /// Number of objects recognized as audio nodes or devices.
+    pub parsed: u64,
+    pub ignored: u64,
+    pub errors: u64,
+
+    /// Number of seen objects per PipeWire object type.
+    pub by_type: HashMap<String, u64>,
+}
+
+#[allow(dead_code)]
+/// PwErrorKind is an error reported by PipeWire.
+#[derive(Debug, Clone)]
+pub struct PwErrorKind {
+    pub msg: String,
+
+    /// Fatal errors mean that connection to PipeWire is broken.
+    pub is_fatal: bool,
+}
+
+/// DefaultKind is a kind of default device selected in session manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultKind {
+    Sink,
+    Source,
+}
+
+impl DefaultKind {
+    /// Returns default device kind for a key of `default` metadata object.
+    pub fn from_metadata_key(key: &str) -> Option<Self> {
+        match key {
+            "default.audio.sink" => Some(Self::Sink),
+            "default.audio.source" => Some(Self::Source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ActionType {
+    EntryAdd(u32, Entry),
+    EntryUpdate(u32, Entry),
+    GlobalRemove(u32),
+    /// First volume state reported after subscription.
+    VolumeChangeRaw(u32, VolumeInfo),
+
+    /// Volume state change with a difference from previous state.
+    VolumeChangeWithDelta(u32, VolumeInfo, VolumeInfoDelta),
+    FormatChanged(u32, AudioFormat),
+
+    /// Graph quantum in frames changed, reported by `settings` metadata.
+    QuantumChanged(u32),
+    StartupStats(GlobalChangeStats),
+
+    /// New link between ports. Links are identified by registry global ID.
+    LinkAdd(LinkEntry),
+    LinkStateChanged(u32, LinkState),
+    LinkRemove(u32),
+
+    /// New application connected to PipeWire. Clients are identified by registry global ID.
+    ClientAdd(u32, ClientEntry),
+    ClientRemove(u32),
+
+    /// Default device changed. Device is identified by node name.
+    DefaultChanged(DefaultKind, String),
+
+    /// Version of PipeWire daemon reported after connection.
+    PipeWireVersion(String),
+
+    /// PipeWire error related to an object.
+    Error(u32, PwErrorKind),
+
+    /// Connection to PipeWire is lost. Tracked objects are gone and are reported
+    /// again after reconnect.
+    Disconnected,
+    Shutdown,
+}
+
+impl ActionType {
+    /// Returns ID of an object action is related to.
+    pub fn object_id(&self) -> Option<u32> {
+        match self {
+            ActionType::EntryAdd(oid, _)
+            | ActionType::EntryUpdate(oid, _)
+            | ActionType::VolumeChangeRaw(oid, _)
+            | ActionType::VolumeChangeWithDelta(oid, ..)
+            | ActionType::FormatChanged(oid, _) => Some(*oid),
+            ActionType::GlobalRemove(_)
+            | ActionType::QuantumChanged(_)
+            | ActionType::StartupStats(_)
+            | ActionType::LinkAdd(_)
+            | ActionType::LinkStateChanged(..)
+            | ActionType::LinkRemove(_)
+            | ActionType::ClientAdd(..)
+            | ActionType::ClientRemove(_)
+            | ActionType::DefaultChanged(..)
+            | ActionType::PipeWireVersion(_)
+            | ActionType::Error(..)
+            | ActionType::Disconnected
+            | ActionType::Shutdown => None,
+        }
+    }
+
+    /// Returns whether action is a volume change event.
+    pub fn is_volume_change(&self) -> bool {
+        matches!(
+            self,
+            ActionType::VolumeChangeRaw(..) | ActionType::VolumeChangeWithDelta(..)
+        )
+    }
+}
+
+/// TimestampedAction is an action with a time when it was produced.
+#[derive(Debug)]
+pub struct TimestampedAction<T> {
+    pub action: T,
+    pub timestamp: Instant,
+}
+
+impl<T> TimestampedAction<T> {
+    pub fn new(action: T) -> Self {
+        Self {
+            action,
+            timestamp: Instant::now(),
+        }
+    }
+
+    /// Returns time elapsed since action was produced.
+    pub fn age(&self) -> Duration {
+        self.timestamp.elapsed()
+    }
+}
+
+impl<T> From<T> for TimestampedAction<T> {
+    fn from(action: T) -> Self {
+        Self::new(action)
+    }
+}
+
+/// Capacity of [EventBus] channel.
+///
+/// Slow receivers lose the oldest events when the channel is full.
+const EVENT_BUS_CAPACITY: usize = 64;
+
+/// EventBus delivers actions to additional consumers besides the main loop.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: tokio::sync::broadcast::Sender<ActionType>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, _) = tokio::sync::broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl EventBus {
+    /// Returns a new receiver of actions published after the call.
+    #[allow(dead_code)]
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<ActionType> {
+        self.tx.subscribe()
+    }
+
+    /// Sends action to all receivers.
+    ///
+    /// Action isn't cloned if there are no receivers.
+    pub fn publish(&self, action: &ActionType) {
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(action.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_kind_from_metadata_key() {
+        assert_eq!(
+            DefaultKind::from_metadata_key("default.audio.sink"),
+            Some(DefaultKind::Sink)
+        );
+        assert_eq!(
+            DefaultKind::from_metadata_key("default.audio.source"),
+            Some(DefaultKind::Source)
+        );
+        assert_eq!(
+            DefaultKind::from_metadata_key("default.configured.audio.sink"),
+            None
+        );
+    }
+
+    #[test]
+    fn object_id_of_entry_actions() {
+        let vol = VolumeInfo::from_percentage(50.0, 2);
+        assert_eq!(ActionType::VolumeChangeRaw(7, vol).object_id(), Some(7));
+        assert_eq!(
+            ActionType::EntryAdd(8, Entry::default()).object_id(),
+            Some(8)
+        );
+
+        // Removal is reported with registry global ID, not entry ID.
+        assert_eq!(ActionType::GlobalRemove(9).object_id(), None);
+        assert_eq!(ActionType::Disconnected.object_id(), None);
+    }
+
+    #[test]
+    fn volume_change_actions() {
+        let vol = VolumeInfo::from_percentage(50.0, 2);
+        let delta = VolumeInfoDelta::default();
+        assert!(ActionType::VolumeChangeRaw(1, vol.clone()).is_volume_change());
+        assert!(ActionType::VolumeChangeWithDelta(1, vol, delta).is_volume_change());
+        assert!(!ActionType::GlobalRemove(1).is_volume_change());
+    }
+}
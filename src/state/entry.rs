@@ -0,0 +1,761 @@
+//! Audio devices, nodes, links and clients tracked by the daemon.
+
+use std::{borrow::Cow, collections::HashMap, time::Instant};
+
+use super::VolumeInfo;
+use crate::{
+    config::IconThresholds,
+    utils::{self, NameTransform, PWGlobalObject},
+};
+pub use pipewire::spa::param::audio::AudioFormat as SpaAudioFormat;
+use pipewire::{spa::utils::dict::DictRef, types::ObjectType};
+use tracing::debug;
+
+/// Node labels which don't say anything about a device.
+const GENERIC_NODE_LABELS: &[&str] = &["playback", "capture"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[non_exhaustive]
+pub enum DeviceKind {
+    /// Unknown is fallback value.
+    Unknown,
+
+    /// Device is generic device that can be either source, sink or both.
+    Device,
+
+    /// Sink is output device (e.g. headphones).
+    Sink,
+
+    /// Source is input device (e.g. microphone).
+    Source,
+
+    /// Monitor is a source capturing output of a sink, used to record desktop audio.
+    Monitor,
+
+    /// Duplex is a node that is both input and output (e.g. USB audio interface).
+    Duplex,
+}
+
+impl std::fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Unknown => "Unknown",
+            Self::Device => "Device",
+            Self::Sink => "Sink",
+            Self::Source => "Source",
+            Self::Monitor => "Monitor",
+            Self::Duplex => "Duplex",
+        })
+    }
+}
+
+/// DeviceApiClass is an API used to access a device, reported in `device.api` property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceApiClass {
+    Alsa,
+    Bluetooth5,
+    Jack,
+    PulseAudio,
+    Unknown(String),
+}
+
+impl From<&str> for DeviceApiClass {
+    fn from(api: &str) -> Self {
+        match api {
+            "alsa" => Self::Alsa,
+            "bluez5" => Self::Bluetooth5,
+            "jack" => Self::Jack,
+            "pulse" => Self::PulseAudio,
+            _ => Self::Unknown(api.to_string()),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// DeviceKindSet is a set of device kinds used for filtering.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DeviceKindSet: u8 {
+        const UNKNOWN = 1 << 0;
+        const DEVICE = 1 << 1;
+        const SINK = 1 << 2;
+        const SOURCE = 1 << 3;
+        const MONITOR = 1 << 4;
+        const DUPLEX = 1 << 5;
+
+        /// Kinds that can play audio.
+        const OUTPUTS = Self::SINK.bits() | Self::DUPLEX.bits();
+
+        /// Kinds that can capture audio.
+        const INPUTS = Self::SOURCE.bits() | Self::DUPLEX.bits();
+
+        const ALL = Self::UNKNOWN.bits()
+            | Self::DEVICE.bits()
+            | Self::SINK.bits()
+            | Self::SOURCE.bits()
+            | Self::MONITOR.bits()
+            | Self::DUPLEX.bits();
+    }
+}
+
+impl DeviceKindSet {
+    /// Returns whether set includes a device kind.
+    pub fn contains_kind(&self, kind: &DeviceKind) -> bool {
+        self.contains(kind.into())
+    }
+}
+
+impl From<&DeviceKind> for DeviceKindSet {
+    fn from(kind: &DeviceKind) -> Self {
+        match kind {
+            DeviceKind::Unknown => DeviceKindSet::UNKNOWN,
+            DeviceKind::Device => DeviceKindSet::DEVICE,
+            DeviceKind::Sink => DeviceKindSet::SINK,
+            DeviceKind::Source => DeviceKindSet::SOURCE,
+            DeviceKind::Monitor => DeviceKindSet::MONITOR,
+            DeviceKind::Duplex => DeviceKindSet::DUPLEX,
+        }
+    }
+}
+
+impl From<&str> for DeviceKind {
+    fn from(value: &str) -> Self {
+        match value {
+            "Audio/Sink" => DeviceKind::Sink,
+            "Audio/Source" => DeviceKind::Source,
+            "Audio/Sink/Monitor" => DeviceKind::Monitor,
+            "Audio/Duplex" => DeviceKind::Duplex,
+            "Audio/Device" => DeviceKind::Device,
+            _ => DeviceKind::Unknown,
+        }
+    }
+}
+
+/// AudioFormat is a negotiated node audio format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: Option<u32>,
+    pub sample_format: Option<SpaAudioFormat>,
+}
+
+/// FilterRule describes criteria to match entries.
+///
+/// Unset fields match any entry.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct FilterRule {
+    pub kind: Option<DeviceKind>,
+
+    /// Matches entry name or label.
+    pub name: Option<String>,
+    pub is_muted: Option<bool>,
+
+    /// Matches entries with volume (in percent) below the value.
+    pub volume_below: Option<f32>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u32,
+    pub is_node: bool,
+    pub device_id: Option<u32>,
+    pub name: Option<String>,
+    pub label: Option<String>,
+    pub description: Option<String>,
+
+    /// Custom display name set by user.
+    pub alias: Option<String>,
+
+    /// Value of `object.serial` property.
+    ///
+    /// Unlike object ID, serial isn't reused during PipeWire session.
+    pub object_serial: Option<u64>,
+
+    /// Value of `application.id` property.
+    pub app_id: Option<String>,
+
+    /// Value of `application.process.id` property.
+    pub app_pid: Option<u32>,
+
+    /// Name of an application owning the node, taken from its PipeWire client.
+    pub app_name: Option<String>,
+    pub kind: DeviceKind,
+
+    /// API used to access a device.
+    pub device_api: Option<DeviceApiClass>,
+
+    /// Negotiated Bluetooth codec, e.g. `sbc` or `ldac`.
+    pub codec: Option<String>,
+    pub volume: Option<VolumeInfo>,
+
+    /// Time of the last volume update, see [Entry::update_volume].
+    pub last_volume_change: Option<Instant>,
+
+    /// Sample rate of negotiated format. Available only for active nodes.
+    pub sample_rate: Option<u32>,
+
+    /// Sample format of negotiated format. Available only for active nodes.
+    pub sample_format: Option<SpaAudioFormat>,
+
+    /// Latency requested by a node in frames, from `node.latency` prop.
+    pub node_latency: Option<u32>,
+
+    /// Buffer size (quantum) node is processed with, in frames.
+    ///
+    /// Taken from `clock.quantum` of `settings` metadata, falls back to `node_latency`
+    /// until the quantum is known. See [Entry::update_buffer_size].
+    pub buffer_size: Option<u32>,
+
+    /// Node latency in milliseconds.
+    ///
+    /// Computed from `buffer_size` and `sample_rate` when both are known.
+    pub latency_ms: Option<f32>,
+}
+
+/// Entry ID used by [Entry::default].
+const SENTINEL_ENTRY_ID: u32 = u32::MAX;
+
+impl Default for Entry {
+    /// Returns blank entry with an invalid sentinel ID.
+    ///
+    /// ID must be replaced before use.
+    fn default() -> Self {
+        Self {
+            id: SENTINEL_ENTRY_ID,
+            is_node: false,
+            device_id: None,
+            name: None,
+            label: None,
+            description: None,
+            alias: None,
+            object_serial: None,
+            app_id: None,
+            app_pid: None,
+            app_name: None,
+            kind: DeviceKind::Unknown,
+            device_api: None,
+            codec: None,
+            volume: None,
+            last_volume_change: None,
+            sample_rate: None,
+            sample_format: None,
+            node_latency: None,
+            buffer_size: None,
+            latency_ms: None,
+        }
+    }
+}
+
+impl Entry {
+    /// Builds entry from PipeWire global object.
+    ///
+    /// Returns `None` if object has no props or [Entry::from_props] returns `None`.
+    pub fn from_global_object(o: &PWGlobalObject) -> Option<Self> {
+        let props = match o.props {
+            Some(props) => props,
+            None => {
+                debug!(obj_id = o.id, "ignore object without props");
+                return None;
+            }
+        };
+
+        Self::from_props(o.id, &o.type_, props)
+    }
+
+    /// Builds entry from props of an object with the given global ID.
+    ///
+    /// Props come either from a registry global or from an info event of a bound object.
+    ///
+    /// Returns `None` if:
+    /// - object is neither node nor device;
+    /// - node or device isn't an audio one, see [utils::classify_audio_node]
+    ///   and [utils::classify_audio_device].
+    pub fn from_props(id: u32, type_: &ObjectType, props: &DictRef) -> Option<Self> {
+        let shared = Entry {
+            id,
+            device_id: props.get("device.id").and_then(|v| v.parse::<u32>().ok()),
+            object_serial: props
+                .get("object.serial")
+                .and_then(|v| v.parse::<u64>().ok()),
+            app_id: props.get("application.id").map(|v| v.to_string()),
+            app_pid: props
+                .get("application.process.id")
+                .and_then(|v| v.parse::<u32>().ok()),
+            device_api: props.get("device.api").map(DeviceApiClass::from),
+            codec: props.get("api.bluez5.codec").map(|v| v.to_string()),
+            ..Default::default()
+        };
+
+        let entry = match type_ {
+            ObjectType::Node => Entry {
+                kind: utils::classify_audio_node(&Some(props))?,
+                is_node: true,
+                name: props.get("node.name").map(|v| v.to_string()),
+                label: props
+                    .get("node.nick")
+                    .or_else(|| props.get("node.description"))
+                    .map(|v| v.to_string()),
+                description: props.get("node.description").map(|v| v.to_string()),
+                node_latency: props.get("node.latency").and_then(parse_node_latency),
+                ..shared
+            },
+            ObjectType::Device => Entry {
+                kind: utils::classify_audio_device(&Some(props))?,
+                name: props.get("device.name").map(|v| v.to_string()),
+                label: props
+                    .get("device.description")
+                    .or_else(|| props.get("device.name"))
+                    .map(|v| v.to_string()),
+                description: props.get("device.description").map(|v| v.to_string()),
+                ..shared
+            },
+            _ => return None,
+        };
+
+        Some(entry)
+    }
+
+    /// Sets alias if entry name or label matches one of alias keys.
+    ///
+    /// Name takes precedence over label.
+    pub fn apply_aliases(&mut self, aliases: &HashMap<String, String>) {
+        self.alias = [self.name.as_ref(), self.label.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|key| aliases.get(key))
+            .cloned();
+    }
+
+    /// Replaces label with a display name produced from the raw object name.
+    pub fn apply_name_transform(&mut self, transform: &NameTransform) {
+        if let Some(name) = self.name.as_deref() {
+            self.label = Some(transform(name));
+        }
+    }
+
+    /// Returns whether entry is a Bluetooth device or node.
+    #[allow(dead_code)]
+    pub fn is_bluetooth(&self) -> bool {
+        self.device_api == Some(DeviceApiClass::Bluetooth5)
+    }
+
+    /// Returns whether entry has a sentinel ID set by [Entry::default].
+    #[allow(dead_code)]
+    pub fn is_sentinel(&self) -> bool {
+        self.id == SENTINEL_ENTRY_ID
+    }
+
+    /// Returns whether entry satisfies all criteria of a rule.
+    pub fn matches_rule(&self, rule: &FilterRule) -> bool {
+        if let Some(kind) = &rule.kind
+            && *kind != self.kind
+        {
+            return false;
+        }
+
+        if let Some(name) = &rule.name
+            && self.name.as_ref() != Some(name)
+            && self.label.as_ref() != Some(name)
+        {
+            return false;
+        }
+
+        if let Some(is_muted) = rule.is_muted {
+            let muted = self.volume.as_ref().and_then(|v| v.mute).unwrap_or(false);
+            if muted != is_muted {
+                return false;
+            }
+        }
+
+        if let Some(threshold) = rule.volume_below {
+            match self.volume.as_ref().and_then(|v| v.volume) {
+                Some(volume) if volume < threshold => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Updates name, label, alias and other props-derived fields from a freshly parsed entry.
+    ///
+    /// Returns list of changed fields.
+    pub fn update_props(&mut self, other: Entry) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.name != other.name {
+            self.name = other.name;
+            changed.push("name");
+        }
+
+        if self.label != other.label {
+            self.label = other.label;
+            changed.push("label");
+        }
+
+        if self.alias != other.alias {
+            self.alias = other.alias;
+            changed.push("alias");
+        }
+
+        if self.description != other.description {
+            self.description = other.description;
+            changed.push("description");
+        }
+
+        if self.codec != other.codec {
+            self.codec = other.codec;
+            changed.push("codec");
+        }
+
+        if self.node_latency != other.node_latency {
+            self.node_latency = other.node_latency;
+            changed.push("node_latency");
+        }
+
+        changed
+    }
+
+    /// Sets reported volume and records time of the change.
+    pub fn update_volume(&mut self, vol: VolumeInfo) {
+        self.volume = Some(vol);
+        self.last_volume_change = Some(Instant::now());
+    }
+
+    /// Sets buffer size of a node from graph quantum, or from latency requested by the node
+    /// if quantum is unknown.
+    ///
+    /// Devices aren't processed by the graph directly and have no buffer size.
+    pub fn update_buffer_size(&mut self, clock_quantum: Option<u32>) {
+        if !self.is_node {
+            return;
+        }
+
+        self.buffer_size = clock_quantum.or(self.node_latency);
+        self.update_latency_ms();
+    }
+
+    /// Recalculates `latency_ms` from current buffer size and sample rate.
+    pub fn update_latency_ms(&mut self) {
+        self.latency_ms = match (self.buffer_size, self.sample_rate) {
+            (Some(frames), Some(rate)) if rate > 0 => Some(frames as f32 * 1000.0 / rate as f32),
+            _ => None,
+        };
+    }
+
+    /// Replaces generic node label with a description of its parent device.
+    ///
+    /// Returns true if label was replaced.
+    pub fn merge_with_device_info(&mut self, device: &Entry) -> bool {
+        let is_generic = self.label.as_deref().is_none_or(|l| {
+            GENERIC_NODE_LABELS
+                .iter()
+                .any(|g| l.eq_ignore_ascii_case(g))
+        });
+        if !self.is_node || !is_generic || device.description.is_none() {
+            return false;
+        }
+
+        self.label = device.description.clone();
+        true
+    }
+
+    /// Returns notification label and symbolic icon name for a volume state.
+    pub fn display_name_with_icon(
+        &self,
+        vol: &VolumeInfo,
+        thresholds: &IconThresholds,
+    ) -> (String, String) {
+        let label = self.format_label();
+        match (vol.mute, vol.effective_volume()) {
+            (Some(true), _) => (
+                format!("{label} - Muted"),
+                "audio-volume-muted-symbolic".to_string(),
+            ),
+            (_, Some(v)) => (
+                format!("{label} - {}%", v.round() as i32),
+                thresholds.icon_for(v).to_string(),
+            ),
+            _ => (label.to_string(), "audio-volume-high-symbolic".to_string()),
+        }
+    }
+
+    /// Sets application name from a client which started the node.
+    ///
+    /// Client is matched by process ID. Returns true if application name was set.
+    pub fn apply_client_info(&mut self, client: &ClientEntry) -> bool {
+        if self.app_pid.is_none() || self.app_pid != client.pid || client.app_name.is_none() {
+            return false;
+        }
+
+        self.app_name = client.app_name.clone();
+        true
+    }
+
+    /// Returns display name of an entry.
+    ///
+    /// Entries without any name are labeled by object serial or ID.
+    pub fn format_label(&self) -> Cow<'_, str> {
+        let name = self
+            .alias
+            .as_ref()
+            .or(self.label.as_ref())
+            .or(self.description.as_ref())
+            .or(self.app_name.as_ref())
+            .or(self.name.as_ref());
+
+        match (name, self.object_serial) {
+            (Some(name), _) => Cow::Borrowed(name),
+            (None, Some(serial)) => Cow::Owned(format!("Serial#{serial}")),
+            (None, None) => Cow::Owned(format!("<id:{}>", self.id)),
+        }
+    }
+
+    /// Returns display name with entry type, ID and kind, e.g. `Built-in Audio (node #42, Sink)`.
+    ///
+    /// Used in logs and diagnostic dumps where context matters more than brevity.
+    pub fn format_label_long(&self) -> String {
+        let type_ = if self.is_node { "node" } else { "device" };
+        format!(
+            "{} ({type_} #{}, {})",
+            self.format_label(),
+            self.id,
+            self.kind
+        )
+    }
+}
+
+/// Parses frames count of `node.latency` prop value, e.g. `256/48000`.
+fn parse_node_latency(v: &str) -> Option<u32> {
+    let (frames, _rate) = v.split_once('/')?;
+    frames.trim().parse::<u32>().ok().filter(|f| *f > 0)
+}
+
+/// Formats entry as a single line with kind, ID, label and name,
+/// e.g. `[Sink #42] Headphones (alsa_output.pci-0000_00_1f.3.analog-stereo)`.
+///
+/// Name is omitted if it's missing or is the same as label.
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = self.format_label();
+        write!(f, "[{} #{}] {label}", self.kind, self.id)?;
+        match self.name.as_deref() {
+            Some(name) if name != label => write!(f, " ({name})"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// LinkState is a state of a link between ports.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LinkState {
+    /// Link info wasn't received yet.
+    #[default]
+    Unknown,
+    Error(String),
+    Unlinked,
+    Init,
+    Negotiating,
+    Allocating,
+    Paused,
+    Active,
+}
+
+/// LinkEntry is a link between output and input ports of two nodes.
+///
+/// Node and port IDs are registry global IDs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkEntry {
+    pub id: u32,
+    pub output_node: u32,
+    pub output_port: u32,
+    pub input_node: u32,
+    pub input_port: u32,
+    pub state: LinkState,
+}
+
+/// ClientEntry is an application connected to PipeWire.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientEntry {
+    /// Registry global ID.
+    pub id: u32,
+    pub app_name: Option<String>,
+
+    /// Process ID of an application.
+    pub pid: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(label: &str) -> Entry {
+        Entry {
+            id: 42,
+            is_node: true,
+            name: Some("alsa_output.pci-0000_00_1f.3.analog-stereo".to_string()),
+            label: Some(label.to_string()),
+            kind: DeviceKind::Sink,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn device_kind_from_media_class() {
+        assert_eq!(DeviceKind::from("Audio/Sink"), DeviceKind::Sink);
+        assert_eq!(DeviceKind::from("Audio/Sink/Monitor"), DeviceKind::Monitor);
+        assert_eq!(DeviceKind::from("Video/Source"), DeviceKind::Unknown);
+    }
+
+    #[test]
+    fn device_kind_set_groups() {
+        assert!(DeviceKindSet::OUTPUTS.contains_kind(&DeviceKind::Sink));
+        assert!(DeviceKindSet::OUTPUTS.contains_kind(&DeviceKind::Duplex));
+        assert!(!DeviceKindSet::OUTPUTS.contains_kind(&DeviceKind::Source));
+        assert!(!DeviceKindSet::OUTPUTS.contains_kind(&DeviceKind::Device));
+        assert!(DeviceKindSet::INPUTS.contains_kind(&DeviceKind::Source));
+        assert!(!DeviceKindSet::INPUTS.contains_kind(&DeviceKind::Device));
+        assert!(!DeviceKindSet::INPUTS.contains_kind(&DeviceKind::Monitor));
+    }
+
+    #[test]
+    fn matches_rule() {
+        let mut e = node("Headphones");
+        e.volume = Some(VolumeInfo::from_percentage(20.0, 2));
+
+        let rule = FilterRule {
+            kind: Some(DeviceKind::Sink),
+            name: Some("Headphones".to_string()),
+            volume_below: Some(30.0),
+            ..Default::default()
+        };
+        assert!(e.matches_rule(&rule));
+
+        let rule = FilterRule {
+            is_muted: Some(true),
+            ..Default::default()
+        };
+        assert!(!e.matches_rule(&rule));
+    }
+
+    #[test]
+    fn merge_with_device_info_replaces_generic_label() {
+        let device = Entry {
+            description: Some("Built-in Audio".to_string()),
+            ..Default::default()
+        };
+
+        let mut e = node("Playback");
+        assert!(e.merge_with_device_info(&device));
+        assert_eq!(e.label.as_deref(), Some("Built-in Audio"));
+
+        let mut e = node("Headphones");
+        assert!(!e.merge_with_device_info(&device));
+        assert_eq!(e.label.as_deref(), Some("Headphones"));
+    }
+
+    #[test]
+    fn update_props_reports_changed_fields() {
+        let mut e = node("Headphones");
+        let mut updated = e.clone();
+        updated.label = Some("Speakers".to_string());
+        updated.codec = Some("ldac".to_string());
+
+        assert_eq!(e.update_props(updated), vec!["label", "codec"]);
+        assert_eq!(e.update_props(e.clone()), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn update_props_from_props_dict() {
+        let props = pipewire::properties::properties! {
+            "media.class" => "Audio/Sink",
+            "node.name" => "bluez_output.00_11_22_33_44_55.1",
+            "node.description" => "WH-1000XM4",
+            "api.bluez5.codec" => "sbc",
+        };
+        let mut e = Entry::from_props(42, &ObjectType::Node, props.dict()).unwrap();
+        assert_eq!(e.codec.as_deref(), Some("sbc"));
+
+        let props = pipewire::properties::properties! {
+            "media.class" => "Audio/Sink",
+            "node.name" => "bluez_output.00_11_22_33_44_55.1",
+            "node.description" => "WH-1000XM4",
+            "api.bluez5.codec" => "ldac",
+            "node.latency" => "1024/48000",
+        };
+        let updated = Entry::from_props(42, &ObjectType::Node, props.dict()).unwrap();
+        assert_eq!(e.update_props(updated), vec!["codec", "node_latency"]);
+        assert_eq!(e.codec.as_deref(), Some("ldac"));
+        assert_eq!(e.node_latency, Some(1024));
+
+        let props = pipewire::properties::properties! {
+            "media.class" => "Video/Source",
+        };
+        assert!(Entry::from_props(42, &ObjectType::Node, props.dict()).is_none());
+    }
+
+    #[test]
+    fn format_label_fallbacks() {
+        let mut e = node("Headphones");
+        e.alias = Some("Cans".to_string());
+        assert_eq!(e.format_label(), "Cans");
+
+        e.alias = None;
+        assert_eq!(e.format_label(), "Headphones");
+
+        e.label = None;
+        assert_eq!(
+            e.format_label(),
+            "alsa_output.pci-0000_00_1f.3.analog-stereo"
+        );
+
+        e.name = None;
+        e.object_serial = Some(1234);
+        assert_eq!(e.format_label(), "Serial#1234");
+
+        e.object_serial = None;
+        assert_eq!(e.format_label(), "<id:42>");
+    }
+
+    #[test]
+    fn display_name_icon_follows_thresholds() {
+        let e = node("Headphones");
+        let thresholds = IconThresholds::default();
+
+        let (label, icon) =
+            e.display_name_with_icon(&VolumeInfo::from_percentage(20.0, 2), &thresholds);
+        assert_eq!(label, "Headphones - 20%");
+        assert_eq!(icon, "audio-volume-low-symbolic");
+
+        let mut vol = VolumeInfo::from_percentage(80.0, 2);
+        let (_, icon) = e.display_name_with_icon(&vol, &thresholds);
+        assert_eq!(icon, "audio-volume-high-symbolic");
+
+        vol.mute = Some(true);
+        let (label, icon) = e.display_name_with_icon(&vol, &thresholds);
+        assert_eq!(label, "Headphones - Muted");
+        assert_eq!(icon, "audio-volume-muted-symbolic");
+    }
+
+    #[test]
+    fn display_format() {
+        assert_eq!(
+            node("Headphones").to_string(),
+            "[Sink #42] Headphones (alsa_output.pci-0000_00_1f.3.analog-stereo)"
+        );
+
+        let e = Entry {
+            id: 7,
+            kind: DeviceKind::Source,
+            name: Some("Microphone".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(e.to_string(), "[Source #7] Microphone");
+    }
+
+    #[test]
+    fn node_latency_prop() {
+        assert_eq!(parse_node_latency("256/48000"), Some(256));
+        assert_eq!(parse_node_latency("0/48000"), None);
+        assert_eq!(parse_node_latency("256"), None);
+    }
+}
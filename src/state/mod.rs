@@ -0,0 +1,11 @@
+//! Domain types shared by PipeWire listener and the main loop.
+
+mod action;
+mod entry;
+mod store;
+mod volume;
+
+pub use action::*;
+pub use entry::*;
+pub use store::*;
+pub use volume::*;
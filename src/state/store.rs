@@ -0,0 +1,621 @@
+//! Daemon state.
+
+use std::{
+    collections::{HashMap, hash_map},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+
+use super::{
+    ClientEntry, Entry, FilterRule, GlobalChangeStats, LinkEntry, ThresholdConfig, VolumeInfo,
+};
+use crate::{
+    notifier::{Handle as NotificationHandle, Message, NotifyResult},
+    volumestore::VolumeStore,
+};
+use tracing::trace;
+
+/// NotifiedVolume is the last volume notification sent for an entry.
+#[derive(Debug, Clone)]
+pub struct NotifiedVolume {
+    pub time: Instant,
+    pub msg: Message,
+}
+
+impl NotifiedVolume {
+    /// Returns whether message is identical to this one sent less than `cooldown` ago.
+    pub fn is_repeated_by(&self, msg: &Message, cooldown: Duration) -> bool {
+        self.msg == *msg && self.time.elapsed() < cooldown
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+pub struct State {
+    /// Handles of displayed notifications, ordered from the least recently sent.
+    pub notifications: IndexMap<u32, NotificationHandle>,
+    pub devices: HashMap<u32, Entry>,
+    pub nodes: HashMap<u32, Entry>,
+
+    /// Notification send tasks in progress.
+    pub pending_notifications: tokio::task::JoinSet<(u32, NotifyResult)>,
+
+    /// Objects with notification being sent and a message to show after it.
+    pub notifications_in_flight: HashMap<u32, Option<Message>>,
+
+    /// Latest volumes of entries with notification postponed by debounce.
+    pub pending_volumes: HashMap<u32, VolumeInfo>,
+
+    /// End of debounce window per entry. Volume changes within a window are
+    /// accumulated in [State::pending_volumes].
+    pub debounce_deadlines: HashMap<u32, Instant>,
+
+    /// The last volume notification per entry.
+    pub last_notified: HashMap<u32, NotifiedVolume>,
+
+    /// Volume changes are compared with, per entry.
+    ///
+    /// Starts from the first known volume and moves only when a notification is sent,
+    /// so skipped changes add up. See [State::is_significant_volume_change].
+    pub volume_baselines: HashMap<u32, VolumeInfo>,
+
+    /// Number of consecutive failed notification attempts.
+    pub notification_failure_streak: u32,
+
+    /// Time of the last event received per entry.
+    pub last_event_time: HashMap<u32, Instant>,
+
+    /// Links between ports by registry global ID.
+    pub links: HashMap<u32, LinkEntry>,
+
+    /// Applications connected to PipeWire by registry global ID.
+    pub clients: HashMap<u32, ClientEntry>,
+
+    /// Registry objects summary reported after initial sync with PipeWire.
+    pub startup_stats: Option<GlobalChangeStats>,
+
+    /// Version of PipeWire daemon.
+    pub pw_version: Option<String>,
+
+    /// Node name of the default sink selected in session manager.
+    pub default_sink: Option<String>,
+
+    /// Graph quantum in frames from `settings` metadata.
+    pub clock_quantum: Option<u32>,
+
+    /// Number of errors reported by PipeWire.
+    pub pw_error_count: u32,
+
+    /// Entries known before connection to PipeWire was lost.
+    ///
+    /// Merged into the state once entries are reported again after reconnect,
+    /// see [State::merge_from_snapshot].
+    pub reconnect_snapshot: Option<Box<State>>,
+
+    /// Last known volumes persisted by entry name. Not set if volumes aren't restored.
+    pub volume_store: Option<VolumeStore>,
+}
+
+impl State {
+    /// Returns all entries matching a rule.
+    #[allow(dead_code)]
+    pub fn find_matching_entries(&self, rule: &FilterRule) -> Vec<(u32, &Entry)> {
+        self.devices
+            .iter()
+            .filter(|(_, e)| e.matches_rule(rule))
+            .map(|(oid, e)| (*oid, e))
+            .collect()
+    }
+
+    /// Stores handle of a sent notification.
+    ///
+    /// If there are already `max` notifications, the least recently sent one is removed
+    /// and returned to be closed by caller.
+    pub fn insert_notification(
+        &mut self,
+        oid: u32,
+        handle: NotificationHandle,
+        max: usize,
+    ) -> Option<(u32, NotificationHandle)> {
+        self.notifications.shift_remove(&oid);
+        let evicted = if self.notifications.len() >= max {
+            self.notifications.shift_remove_index(0)
+        } else {
+            None
+        };
+
+        self.notifications.insert(oid, handle);
+        evicted
+    }
+
+    /// Returns ID of an entry with specified registry global ID.
+    pub fn find_by_global_id(&self, global_id: u32) -> Option<u32> {
+        self.devices
+            .iter()
+            .find(|(_, e)| e.id == global_id)
+            .map(|(oid, _)| *oid)
+    }
+
+    /// Returns ID of an entry with specified name.
+    ///
+    /// Used to resolve default device names from `default` metadata.
+    pub fn find_by_name(&self, name: &str) -> Option<u32> {
+        self.devices
+            .iter()
+            .find(|(_, e)| e.name.as_deref() == Some(name))
+            .map(|(oid, _)| *oid)
+    }
+
+    /// Maps entry IDs of a snapshot to IDs of entries with the same name in this state.
+    ///
+    /// Entries without a name or with a name shared by several entries are skipped.
+    pub fn map_entries_by_name(&self, snapshot: &State) -> HashMap<u32, u32> {
+        let mut by_name: HashMap<&str, Option<u32>> = HashMap::new();
+        for (oid, e) in &self.devices {
+            if let Some(name) = e.name.as_deref() {
+                by_name
+                    .entry(name)
+                    .and_modify(|v| *v = None)
+                    .or_insert(Some(*oid));
+            }
+        }
+
+        snapshot
+            .devices
+            .iter()
+            .filter_map(|(old_oid, e)| {
+                let new_oid = (*by_name.get(e.name.as_deref()?)?)?;
+                Some((*old_oid, new_oid))
+            })
+            .collect()
+    }
+
+    /// Restores volumes known before reconnect.
+    ///
+    /// `mapping` maps entry IDs of a snapshot to IDs in this state,
+    /// see [State::map_entries_by_name].
+    /// Entries which already received volume state are left untouched.
+    #[tracing::instrument(
+        level = "trace",
+        skip(self, snapshot, mapping),
+        fields(mapped = mapping.len(), notification_count = self.notifications.len()),
+    )]
+    pub fn merge_from_snapshot(&mut self, snapshot: &State, mapping: HashMap<u32, u32>) {
+        for (old_oid, new_oid) in mapping {
+            let Some(vol) = snapshot
+                .devices
+                .get(&old_oid)
+                .and_then(|e| e.volume.as_ref())
+            else {
+                continue;
+            };
+
+            if let Some(e) = self.devices.get_mut(&new_oid)
+                && e.volume.is_none()
+            {
+                trace!(
+                    old_oid,
+                    new_oid,
+                    label = %e.format_label(),
+                    "volume restored"
+                );
+                e.volume = Some(vol.clone());
+            }
+        }
+    }
+
+    /// Returns client of an application with specified process ID.
+    pub fn find_client_by_pid(&self, pid: u32) -> Option<&ClientEntry> {
+        self.clients.values().find(|c| c.pid == Some(pid))
+    }
+
+    /// Returns time of the last volume change of an entry.
+    pub fn last_volume_change(&self, oid: u32) -> Option<Instant> {
+        self.devices.get(&oid)?.last_volume_change
+    }
+
+    /// Returns whether volume differs enough from the volume baseline of an entry.
+    ///
+    /// Baseline is seeded from the current entry volume on the first call, so small
+    /// changes are notified once they add up to the threshold.
+    /// Returns `None` if there is nothing to compare with.
+    pub fn is_significant_volume_change(
+        &mut self,
+        oid: u32,
+        vol: &VolumeInfo,
+        cfg: &ThresholdConfig,
+    ) -> Option<bool> {
+        let prev = match self.volume_baselines.entry(oid) {
+            hash_map::Entry::Occupied(e) => e.into_mut(),
+            hash_map::Entry::Vacant(e) => e.insert(self.devices.get(&oid)?.volume.clone()?),
+        };
+
+        Some(vol.is_significant_change_from(prev, cfg))
+    }
+
+    /// Records volume of a sent notification as a new baseline of an entry.
+    pub fn set_notified_volume(&mut self, oid: u32, vol: VolumeInfo, msg: Message) {
+        let notified = NotifiedVolume {
+            time: Instant::now(),
+            msg,
+        };
+        self.last_notified.insert(oid, notified);
+        self.volume_baselines.insert(oid, vol);
+    }
+
+    /// Returns the earliest end of debounce window.
+    pub fn next_debounce_deadline(&self) -> Option<Instant> {
+        self.debounce_deadlines.values().min().copied()
+    }
+
+    /// Removes debounce windows ended before `now` and returns volumes postponed by them.
+    pub fn take_debounced_volumes(&mut self, now: Instant) -> Vec<(u32, VolumeInfo)> {
+        let expired: Vec<_> = self
+            .debounce_deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(oid, _)| *oid)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|oid| {
+                self.debounce_deadlines.remove(&oid);
+                self.pending_volumes.remove(&oid).map(|vol| (oid, vol))
+            })
+            .collect()
+    }
+
+    /// Records time of the last event received for an entry.
+    pub fn touch(&mut self, oid: u32) {
+        self.last_event_time.insert(oid, Instant::now());
+    }
+
+    /// Removes entries which didn't receive any event for longer than `max_age`.
+    ///
+    /// Guards against entries left behind when PipeWire misses a removal event.
+    /// Notifications of removed entries are kept and should be closed by caller.
+    #[tracing::instrument(
+        level = "trace",
+        skip(self),
+        fields(notification_count = self.notifications.len()),
+    )]
+    pub fn evict_stale_devices(&mut self, max_age: Duration) -> Vec<(u32, Entry)> {
+        let stale: Vec<_> = self
+            .devices
+            .keys()
+            .copied()
+            .filter(|oid| {
+                let last_seen = self
+                    .last_event_time
+                    .get(oid)
+                    .copied()
+                    .max(self.last_volume_change(*oid));
+                last_seen.is_none_or(|t| t.elapsed() > max_age)
+            })
+            .collect();
+
+        stale
+            .into_iter()
+            .filter_map(|oid| {
+                self.last_event_time.remove(&oid);
+                self.pending_volumes.remove(&oid);
+                self.debounce_deadlines.remove(&oid);
+                self.last_notified.remove(&oid);
+                self.volume_baselines.remove(&oid);
+                self.devices.remove(&oid).map(|entry| (oid, entry))
+            })
+            .collect()
+    }
+
+    /// Removes entry and returns it along with its notification handle, if any.
+    #[tracing::instrument(
+        level = "trace",
+        skip(self, id),
+        fields(oid = *id, label, notification_count = self.notifications.len()),
+    )]
+    pub fn remove_entry(&mut self, id: &u32) -> (Option<NotificationHandle>, Option<Entry>) {
+        self.last_event_time.remove(id);
+        self.pending_volumes.remove(id);
+        self.debounce_deadlines.remove(id);
+        self.last_notified.remove(id);
+        self.volume_baselines.remove(id);
+        let entry = self.devices.remove(id).or_else(|| self.nodes.remove(id));
+        if let Some(e) = &entry {
+            tracing::Span::current().record("label", e.format_label_long().as_str());
+        }
+
+        (self.notifications.shift_remove(id), entry)
+    }
+
+    /// Removes all entries and returns their notification handles.
+    ///
+    /// Handles of notifications being sent are not included, see [State::pending_notifications].
+    #[tracing::instrument(
+        level = "trace",
+        skip(self),
+        fields(entry_count = self.devices.len(), notification_count = self.notifications.len()),
+    )]
+    pub fn clear_entries(&mut self) -> impl Iterator<Item = NotificationHandle> + '_ {
+        self.devices.clear();
+        self.nodes.clear();
+        self.last_event_time.clear();
+        self.pending_volumes.clear();
+        self.debounce_deadlines.clear();
+        self.last_notified.clear();
+        self.volume_baselines.clear();
+        self.notifications.drain(..).map(|(_, handle)| handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u32, name: &str) -> Entry {
+        Entry {
+            id,
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn insert_notification_evicts_least_recent() {
+        let mut state = State::default();
+        assert!(
+            state
+                .insert_notification(1, NotificationHandle::Detached, 2)
+                .is_none()
+        );
+        assert!(
+            state
+                .insert_notification(2, NotificationHandle::Detached, 2)
+                .is_none()
+        );
+
+        // Re-sent notification becomes the most recent one.
+        assert!(
+            state
+                .insert_notification(1, NotificationHandle::Detached, 2)
+                .is_none()
+        );
+
+        let evicted = state.insert_notification(3, NotificationHandle::Detached, 2);
+        assert_eq!(evicted.map(|(oid, _)| oid), Some(2));
+        assert_eq!(
+            state.notifications.keys().copied().collect::<Vec<_>>(),
+            [1, 3]
+        );
+    }
+
+    #[test]
+    fn find_by_name_returns_entry_id() {
+        let mut state = State::default();
+
+        // Entries are keyed by proxy ID, which differs from registry global ID.
+        state.devices.insert(7, entry(42, "speakers"));
+        state.devices.insert(8, entry(43, "headphones"));
+
+        assert_eq!(state.find_by_name("speakers"), Some(7));
+        assert_eq!(state.find_by_global_id(42), Some(7));
+        assert_eq!(state.find_by_name("hdmi"), None);
+    }
+
+    #[test]
+    fn remove_entry_returns_its_notification_handle() {
+        let mut state = State::default();
+        state.devices.insert(1, entry(1, "speakers"));
+        state.devices.insert(2, entry(2, "headphones"));
+        state.insert_notification(1, NotificationHandle::Detached, 5);
+        state.insert_notification(2, NotificationHandle::Detached, 5);
+
+        let (handle, removed) = state.remove_entry(&1);
+        assert!(handle.is_some());
+        assert_eq!(removed.map(|e| e.id), Some(1));
+        assert_eq!(state.notifications.keys().copied().collect::<Vec<_>>(), [2]);
+
+        // Entry without a notification.
+        state.devices.insert(3, entry(3, "hdmi"));
+        let (handle, removed) = state.remove_entry(&3);
+        assert!(handle.is_none());
+        assert_eq!(removed.map(|e| e.id), Some(3));
+
+        let (handle, removed) = state.remove_entry(&1);
+        assert!(handle.is_none() && removed.is_none());
+    }
+
+    #[test]
+    fn clear_entries_returns_remaining_handles() {
+        let mut state = State::default();
+        for id in 1..=3 {
+            state.devices.insert(id, entry(id, "speakers"));
+            state.insert_notification(id, NotificationHandle::Detached, 5);
+        }
+
+        let mut node = entry(4, "alsa_output");
+        node.is_node = true;
+        state.nodes.insert(4, node);
+
+        let (handle, _) = state.remove_entry(&2);
+        assert!(handle.is_some());
+        assert_eq!(state.notifications.len(), 2);
+
+        assert_eq!(state.clear_entries().count(), 2);
+        assert!(state.notifications.is_empty());
+        assert!(state.devices.is_empty());
+        assert!(state.nodes.is_empty());
+    }
+
+    #[test]
+    fn take_debounced_volumes_returns_expired_only() {
+        let mut state = State::default();
+        let now = Instant::now();
+        state.debounce_deadlines.insert(1, now);
+        state
+            .debounce_deadlines
+            .insert(2, now + Duration::from_secs(1));
+        state
+            .pending_volumes
+            .insert(1, VolumeInfo::from_percentage(10.0, 2));
+        state
+            .pending_volumes
+            .insert(2, VolumeInfo::from_percentage(20.0, 2));
+
+        let taken = state.take_debounced_volumes(now);
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, 1);
+        assert_eq!(
+            state.next_debounce_deadline(),
+            Some(now + Duration::from_secs(1))
+        );
+        assert!(state.pending_volumes.contains_key(&2));
+    }
+
+    #[test]
+    fn merge_from_snapshot_restores_volumes_by_name() {
+        let mut snapshot = State::default();
+        let mut old = entry(10, "speakers");
+        old.volume = Some(VolumeInfo::from_percentage(30.0, 2));
+        snapshot.devices.insert(10, old);
+
+        let mut dup = entry(11, "hdmi");
+        dup.volume = Some(VolumeInfo::from_percentage(40.0, 2));
+        snapshot.devices.insert(11, dup);
+
+        let mut state = State::default();
+        state.devices.insert(20, entry(20, "speakers"));
+
+        // Entries with ambiguous names aren't mapped.
+        state.devices.insert(21, entry(21, "hdmi"));
+        state.devices.insert(22, entry(22, "hdmi"));
+
+        let mapping = state.map_entries_by_name(&snapshot);
+        assert_eq!(mapping, HashMap::from([(10, 20)]));
+
+        state.merge_from_snapshot(&snapshot, mapping);
+        assert_eq!(
+            state.devices[&20].volume,
+            Some(VolumeInfo::from_percentage(30.0, 2))
+        );
+        assert!(state.devices[&21].volume.is_none());
+    }
+
+    fn message(summary: &str) -> Message {
+        Message {
+            summary: summary.to_string(),
+            body: None,
+            icon: String::new(),
+            value: None,
+            timeout: Duration::ZERO,
+            transient: false,
+        }
+    }
+
+    fn notified() -> NotifiedVolume {
+        NotifiedVolume {
+            time: Instant::now(),
+            msg: message("Speakers: 50%"),
+        }
+    }
+
+    #[test]
+    fn volume_change_falls_back_to_entry_volume() {
+        let cfg = ThresholdConfig::default();
+        let mut state = State::default();
+        let vol = VolumeInfo::from_percentage(50.0, 2);
+        assert_eq!(state.is_significant_volume_change(1, &vol, &cfg), None);
+
+        let mut e = entry(1, "speakers");
+        e.volume = Some(VolumeInfo::from_percentage(49.0, 2));
+        state.devices.insert(1, e);
+        assert_eq!(
+            state.is_significant_volume_change(1, &vol, &cfg),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn small_volume_changes_add_up_to_threshold() {
+        let cfg = ThresholdConfig {
+            min_volume_change_pct: 3.0,
+            ..Default::default()
+        };
+        let mut state = State::default();
+        let mut e = entry(1, "speakers");
+        e.volume = Some(VolumeInfo::from_percentage(52.0, 2));
+        state.devices.insert(1, e);
+        state.set_notified_volume(
+            1,
+            VolumeInfo::from_percentage(50.0, 2),
+            message("Speakers: 50%"),
+        );
+
+        // Compared with the notified volume rather than the current one.
+        let vol = VolumeInfo::from_percentage(52.0, 2);
+        assert_eq!(
+            state.is_significant_volume_change(1, &vol, &cfg),
+            Some(false)
+        );
+
+        let vol = VolumeInfo::from_percentage(53.0, 2);
+        assert_eq!(
+            state.is_significant_volume_change(1, &vol, &cfg),
+            Some(true)
+        );
+
+        let vol = VolumeInfo::from_percentage(47.0, 2);
+        assert_eq!(
+            state.is_significant_volume_change(1, &vol, &cfg),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn skipped_volume_changes_add_up_before_first_notification() {
+        let cfg = ThresholdConfig {
+            min_volume_change_pct: 1.0,
+            ..Default::default()
+        };
+        let mut state = State::default();
+        let mut e = entry(1, "speakers");
+        e.volume = Some(VolumeInfo::from_percentage(50.0, 2));
+        state.devices.insert(1, e);
+
+        // Entry volume follows every reported change, like in the main loop.
+        let step = |state: &mut State, pct: f32| {
+            let vol = VolumeInfo::from_percentage(pct, 2);
+            let is_significant = state.is_significant_volume_change(1, &vol, &cfg);
+            state
+                .devices
+                .get_mut(&1)
+                .unwrap()
+                .update_volume(vol.clone());
+            if is_significant == Some(true) {
+                state.set_notified_volume(1, vol, message("Speakers"));
+            }
+            is_significant
+        };
+
+        assert_eq!(step(&mut state, 50.6), Some(false));
+        assert_eq!(step(&mut state, 51.2), Some(true));
+        assert_eq!(step(&mut state, 51.8), Some(false));
+    }
+
+    #[test]
+    fn repeated_notification_within_cooldown() {
+        let cooldown = Duration::from_secs(1);
+        let msg = message("Speakers: 50%");
+        let mut last = notified();
+        assert!(last.is_repeated_by(&msg, cooldown));
+        assert!(!last.is_repeated_by(&message("Speakers: 60%"), cooldown));
+        assert!(!last.is_repeated_by(&msg, Duration::ZERO));
+
+        // Cooldown has passed.
+        if let Some(time) = Instant::now().checked_sub(cooldown * 2) {
+            last.time = time;
+            assert!(!last.is_repeated_by(&msg, cooldown));
+        }
+    }
+}
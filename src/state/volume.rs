@@ -0,0 +1,526 @@
+//! Volume state and its changes.
+
+use std::{fmt, io::Cursor};
+
+use pipewire::spa::{
+    pod::{
+        Object, Property, Value as PodValue, ValueArray,
+        serialize::{GenError, PodSerializer},
+    },
+    sys as spa_sys,
+};
+use tracing::warn;
+
+/// Max allowed volume value in percent.
+pub const MAX_VOLUME_PCT: f32 = 150.0;
+
+/// Minimal volume difference (in percent) considered as a change.
+pub const VOLUME_CHANGE_THRESHOLD: f32 = 0.1;
+
+/// Default minimal volume change (in percent) worth a notification.
+///
+/// Suppresses accidental nudges and sub-percent corrections made by plugins.
+pub const MIN_NOTIFY_VOLUME_CHANGE_PCT: f32 = 1.0;
+
+/// Volumes (in percent) closer than epsilon are considered equal.
+///
+/// Reported volumes are rounded to integer percent, so any real change is at least 1%.
+pub const VOLUME_EPSILON: f32 = 0.5;
+
+/// SpaChannelPosition is a SPA audio channel position (`SPA_AUDIO_CHANNEL_*` constant).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpaChannelPosition(pub u32);
+
+impl fmt::Display for SpaChannelPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.0 {
+            spa_sys::SPA_AUDIO_CHANNEL_NA => "NA",
+            spa_sys::SPA_AUDIO_CHANNEL_MONO => "MONO",
+            spa_sys::SPA_AUDIO_CHANNEL_FL => "FL",
+            spa_sys::SPA_AUDIO_CHANNEL_FR => "FR",
+            spa_sys::SPA_AUDIO_CHANNEL_FC => "FC",
+            spa_sys::SPA_AUDIO_CHANNEL_LFE => "LFE",
+            spa_sys::SPA_AUDIO_CHANNEL_SL => "SL",
+            spa_sys::SPA_AUDIO_CHANNEL_SR => "SR",
+            spa_sys::SPA_AUDIO_CHANNEL_FLC => "FLC",
+            spa_sys::SPA_AUDIO_CHANNEL_FRC => "FRC",
+            spa_sys::SPA_AUDIO_CHANNEL_RC => "RC",
+            spa_sys::SPA_AUDIO_CHANNEL_RL => "RL",
+            spa_sys::SPA_AUDIO_CHANNEL_RR => "RR",
+            spa_sys::SPA_AUDIO_CHANNEL_TC => "TC",
+            spa_sys::SPA_AUDIO_CHANNEL_TFL => "TFL",
+            spa_sys::SPA_AUDIO_CHANNEL_TFC => "TFC",
+            spa_sys::SPA_AUDIO_CHANNEL_TFR => "TFR",
+            spa_sys::SPA_AUDIO_CHANNEL_TRL => "TRL",
+            spa_sys::SPA_AUDIO_CHANNEL_TRC => "TRC",
+            spa_sys::SPA_AUDIO_CHANNEL_TRR => "TRR",
+            spa_sys::SPA_AUDIO_CHANNEL_RLC => "RLC",
+            spa_sys::SPA_AUDIO_CHANNEL_RRC => "RRC",
+            spa_sys::SPA_AUDIO_CHANNEL_FLW => "FLW",
+            spa_sys::SPA_AUDIO_CHANNEL_FRW => "FRW",
+            spa_sys::SPA_AUDIO_CHANNEL_LFE2 => "LFE2",
+            spa_sys::SPA_AUDIO_CHANNEL_FLH => "FLH",
+            spa_sys::SPA_AUDIO_CHANNEL_FCH => "FCH",
+            spa_sys::SPA_AUDIO_CHANNEL_FRH => "FRH",
+            spa_sys::SPA_AUDIO_CHANNEL_TFLC => "TFLC",
+            spa_sys::SPA_AUDIO_CHANNEL_TFRC => "TFRC",
+            spa_sys::SPA_AUDIO_CHANNEL_TSL => "TSL",
+            spa_sys::SPA_AUDIO_CHANNEL_TSR => "TSR",
+            spa_sys::SPA_AUDIO_CHANNEL_LLFE => "LLFE",
+            spa_sys::SPA_AUDIO_CHANNEL_RLFE => "RLFE",
+            spa_sys::SPA_AUDIO_CHANNEL_BC => "BC",
+            spa_sys::SPA_AUDIO_CHANNEL_BLC => "BLC",
+            spa_sys::SPA_AUDIO_CHANNEL_BRC => "BRC",
+            pos @ spa_sys::SPA_AUDIO_CHANNEL_START_Aux..=spa_sys::SPA_AUDIO_CHANNEL_LAST_Aux => {
+                return write!(f, "AUX{}", pos - spa_sys::SPA_AUDIO_CHANNEL_START_Aux);
+            }
+            _ => "UNK",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// SpaSerializeError is returned when volume state can't be serialized into a SPA pod.
+#[derive(Debug)]
+pub struct SpaSerializeError(pub GenError);
+
+impl fmt::Display for SpaSerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to serialize volume props: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for SpaSerializeError {}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct VolumeInfo {
+    pub volume: Option<f32>,
+    pub mute: Option<bool>,
+    pub channel_volumes: Vec<f32>,
+
+    /// Channel positions for each item in `channel_volumes`.
+    ///
+    /// Might be empty if PipeWire didn't report a channel map.
+    pub channel_map: Vec<SpaChannelPosition>,
+
+    /// Per channel volumes applied in software on top of hardware volume, in percent.
+    ///
+    /// Values above 100% mean software amplification.
+    pub soft_volumes: Vec<f32>,
+}
+
+/// Volumes are compared within [VOLUME_EPSILON], mute and channel map are compared exactly.
+impl PartialEq for VolumeInfo {
+    fn eq(&self, other: &Self) -> bool {
+        let approx_eq = |a: f32, b: f32| (a - b).abs() <= VOLUME_EPSILON;
+        let volume_eq = match (self.volume, other.volume) {
+            (Some(a), Some(b)) => approx_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+
+        let slice_eq = |a: &[f32], b: &[f32]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| approx_eq(*a, *b))
+        };
+
+        volume_eq
+            && self.mute == other.mute
+            && self.channel_map == other.channel_map
+            && slice_eq(&self.channel_volumes, &other.channel_volumes)
+            && slice_eq(&self.soft_volumes, &other.soft_volumes)
+    }
+}
+
+#[allow(dead_code)]
+impl VolumeInfo {
+    /// Returns volume state with master and all channel volumes set to a percentage.
+    ///
+    /// Percentage is in the same cubic scale as values reported by PipeWire.
+    pub fn from_percentage(pct: f32, channel_count: usize) -> Self {
+        let pct = clamp_volume(pct).unwrap_or(pct);
+        Self {
+            volume: Some(pct),
+            channel_volumes: vec![pct; channel_count],
+            ..Default::default()
+        }
+    }
+
+    /// Same as [VolumeInfo::from_percentage] but accepts percentage in linear scale.
+    pub fn from_percentage_cubic(pct: f32, channel_count: usize) -> Self {
+        let pct = clamp_volume(pct).unwrap_or(pct);
+        Self::from_percentage((pct / 100.0).cbrt() * 100.0, channel_count)
+    }
+
+    /// Serializes node volume state into `SPA_PARAM_Props` object for `set_param()`.
+    ///
+    /// Only channel volumes and mute are written as node master volume is applied
+    /// on top of channel volumes. Master volume is used only if channel volumes are unknown.
+    ///
+    /// Devices don't accept volume in `Props`, it's set via `Route` param instead.
+    ///
+    /// Volume values are converted back from percent into PipeWire linear scale.
+    /// Result can be parsed back with [crate::utils::volume_from_pod].
+    pub fn to_spa_props_pod(&self) -> Result<Vec<u8>, SpaSerializeError> {
+        let to_linear = |pct: f32| (pct / 100.0).powi(3);
+        let mut properties = Vec::with_capacity(2);
+
+        if let Some(mute) = self.mute {
+            properties.push(Property::new(spa_sys::SPA_PROP_mute, PodValue::Bool(mute)));
+        }
+
+        if !self.channel_volumes.is_empty() {
+            let volumes = self.channel_volumes.iter().map(|v| to_linear(*v)).collect();
+            properties.push(Property::new(
+                spa_sys::SPA_PROP_channelVolumes,
+                PodValue::ValueArray(ValueArray::Float(volumes)),
+            ));
+        } else if let Some(vol) = self.volume {
+            properties.push(Property::new(
+                spa_sys::SPA_PROP_volume,
+                PodValue::Float(to_linear(vol)),
+            ));
+        }
+
+        let obj = PodValue::Object(Object {
+            type_: spa_sys::SPA_TYPE_OBJECT_Props,
+            id: spa_sys::SPA_PARAM_Props,
+            properties,
+        });
+
+        let (cursor, _) =
+            PodSerializer::serialize(Cursor::new(Vec::new()), &obj).map_err(SpaSerializeError)?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Clamps master and channel volumes to `[0, MAX_VOLUME_PCT]` range.
+    ///
+    /// Guards against garbage values produced by buggy drivers.
+    pub fn normalize_channel_volumes(&mut self, oid: u32) {
+        if let Some(vol) = self.volume
+            && let Some(clamped) = clamp_volume(vol)
+        {
+            warn!(oid, vol, clamped, "master volume is out of range, clamped");
+            self.volume = Some(clamped);
+        }
+
+        for (i, vol) in self.channel_volumes.iter_mut().enumerate() {
+            if let Some(clamped) = clamp_volume(*vol) {
+                warn!(
+                    oid,
+                    channel = i,
+                    vol,
+                    clamped,
+                    "channel volume is out of range, clamped"
+                );
+                *vol = clamped;
+            }
+        }
+    }
+
+    /// Computes difference between this and previous volume state.
+    ///
+    /// Missing values are treated as zero.
+    pub fn delta_from(&self, other: &VolumeInfo) -> VolumeInfoDelta {
+        let volume_delta = match (self.volume, other.volume) {
+            (None, None) => None,
+            (new, old) => Some(new.unwrap_or(0.0) - old.unwrap_or(0.0)),
+        };
+
+        let channels = self.channel_volumes.len().max(other.channel_volumes.len());
+        let channel_deltas = (0..channels)
+            .map(|i| {
+                self.channel_volumes.get(i).copied().unwrap_or(0.0)
+                    - other.channel_volumes.get(i).copied().unwrap_or(0.0)
+            })
+            .collect();
+
+        VolumeInfoDelta {
+            volume_delta,
+            mute_changed: self.mute.unwrap_or(false) != other.mute.unwrap_or(false),
+            channel_deltas,
+        }
+    }
+
+    /// Returns whether volume state differs enough from a previous one to notify about it.
+    ///
+    /// Balance is an offset of each channel from the first one.
+    pub fn is_significant_change_from(&self, other: &VolumeInfo, cfg: &ThresholdConfig) -> bool {
+        // Volumes are integer-rounded, so a change of exactly the threshold is compared exactly.
+        let exceeds = |d: f32| d.abs() > cfg.epsilon && d.abs() >= cfg.min_volume_change_pct;
+
+        if cfg.check_mute && self.mute.unwrap_or(false) != other.mute.unwrap_or(false) {
+            return true;
+        }
+
+        let volume_delta = match (self.effective_volume(), other.effective_volume()) {
+            (None, None) => 0.0,
+            (new, old) => new.unwrap_or(0.0) - old.unwrap_or(0.0),
+        };
+        if exceeds(volume_delta) {
+            return true;
+        }
+
+        if !cfg.check_balance || self.channel_volumes.len() != other.channel_volumes.len() {
+            return false;
+        }
+
+        self.channel_offsets()
+            .zip(other.channel_offsets())
+            .any(|(new, old)| exceeds(new - old))
+    }
+
+    /// Returns offset of each channel volume from the first channel.
+    fn channel_offsets(&self) -> impl Iterator<Item = f32> + '_ {
+        let first = self.channel_volumes.first().copied().unwrap_or(0.0);
+        self.channel_volumes.iter().map(move |v| v - first)
+    }
+
+    /// Formats per channel values, e.g. `FL: 50%, FR: 50%`.
+    ///
+    /// Channels are numbered if channel map doesn't match values.
+    fn format_channels(&self, values: &[f32]) -> String {
+        let has_names = self.channel_map.len() == values.len();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| match has_names {
+                true => format!("{}: {v:.0}%", self.channel_map[i]),
+                false => format!("Ch{}: {v:.0}%", i + 1),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns master volume or volume of the first channel if master volume is not set.
+    pub fn effective_volume(&self) -> Option<f32> {
+        self.volume
+            .or_else(|| self.channel_volumes.first().copied())
+    }
+
+    /// Returns true if software amplification or attenuation is applied to any channel.
+    ///
+    /// Soft volumes are at 100% otherwise.
+    pub fn has_soft_volume(&self) -> bool {
+        self.soft_volumes
+            .iter()
+            .any(|v| (v - 100.0).abs() > VOLUME_EPSILON)
+    }
+
+    pub fn format_display(&self) -> Option<String> {
+        let mut parts = Vec::new();
+
+        if let Some(vol) = self.volume {
+            parts.push(format!("Volume: {vol:.0}%"));
+        }
+
+        if let Some(m) = self.mute {
+            parts.push(format!("Mute: {}", if m { "ON" } else { "OFF" }));
+        }
+
+        if !self.channel_volumes.is_empty() {
+            parts.push(format!(
+                "Channels: [{}]",
+                self.format_channels(&self.channel_volumes)
+            ));
+        }
+
+        if self.has_soft_volume() {
+            parts.push(format!(
+                "Soft: [{}]",
+                self.format_channels(&self.soft_volumes)
+            ));
+        }
+
+        if parts.is_empty() {
+            Some("Property changed".to_string())
+        } else {
+            Some(parts.join(" | "))
+        }
+    }
+}
+
+/// VolumeDirection is a direction of volume change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeDirection {
+    Up,
+    Down,
+    NoChange,
+}
+
+/// ThresholdConfig defines which volume changes are worth a notification.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdConfig {
+    /// Minimal volume change in percent.
+    pub min_volume_change_pct: f32,
+
+    /// Values closer than epsilon are considered equal.
+    pub epsilon: f32,
+
+    /// Mute toggle is always significant.
+    pub check_mute: bool,
+
+    /// Change of balance between channels is significant.
+    pub check_balance: bool,
+}
+
+impl Default for ThresholdConfig {
+    fn default() -> Self {
+        Self {
+            min_volume_change_pct: MIN_NOTIFY_VOLUME_CHANGE_PCT,
+            epsilon: VOLUME_EPSILON,
+            check_mute: true,
+            check_balance: true,
+        }
+    }
+}
+
+/// VolumeInfoDelta is a difference between two volume states.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VolumeInfoDelta {
+    pub volume_delta: Option<f32>,
+    pub mute_changed: bool,
+    pub channel_deltas: Vec<f32>,
+}
+
+impl VolumeInfoDelta {
+    /// Returns true if mute state changed or any volume delta exceeds the threshold.
+    pub fn is_significant(&self, threshold: f32) -> bool {
+        self.mute_changed
+            || self.volume_delta.is_some_and(|d| d.abs() > threshold)
+            || self.channel_deltas.iter().any(|d| d.abs() > threshold)
+    }
+
+    /// Returns direction of master volume change or of the first channel as a fallback.
+    ///
+    /// Returns `None` if there is no volume information.
+    pub fn direction(&self) -> Option<VolumeDirection> {
+        let delta = self
+            .volume_delta
+            .or_else(|| self.channel_deltas.first().copied())?;
+
+        Some(if delta > 0.0 {
+            VolumeDirection::Up
+        } else if delta < 0.0 {
+            VolumeDirection::Down
+        } else {
+            VolumeDirection::NoChange
+        })
+    }
+}
+
+/// Returns clamped volume value if it's out of range.
+fn clamp_volume(vol: f32) -> Option<f32> {
+    if vol.is_nan() {
+        return Some(0.0);
+    }
+
+    let clamped = vol.clamp(0.0, MAX_VOLUME_PCT);
+    (clamped != vol).then_some(clamped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(pct: f32, mute: bool, channels: &[f32]) -> VolumeInfo {
+        VolumeInfo {
+            volume: Some(pct),
+            mute: Some(mute),
+            channel_volumes: channels.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn equal_volume_is_not_significant() {
+        let cfg = ThresholdConfig::default();
+        let v = volume(50.0, false, &[50.0, 50.0]);
+        assert!(!v.is_significant_change_from(&v.clone(), &cfg));
+    }
+
+    #[test]
+    fn epsilon_close_volume_is_not_significant() {
+        let cfg = ThresholdConfig::default();
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(50.0 + cfg.epsilon / 2.0, false, &[50.0, 50.0]);
+        assert!(!new.is_significant_change_from(&old, &cfg));
+    }
+
+    #[test]
+    fn change_of_exactly_threshold_is_significant() {
+        let cfg = ThresholdConfig::default();
+        let old = volume(50.0, false, &[]);
+        let new = volume(50.0 + cfg.min_volume_change_pct, false, &[]);
+        assert!(new.is_significant_change_from(&old, &cfg));
+    }
+
+    #[test]
+    fn mute_toggle_is_significant() {
+        let cfg = ThresholdConfig::default();
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(50.0, true, &[50.0, 50.0]);
+        assert!(new.is_significant_change_from(&old, &cfg));
+
+        let cfg = ThresholdConfig {
+            check_mute: false,
+            ..Default::default()
+        };
+        assert!(!new.is_significant_change_from(&old, &cfg));
+    }
+
+    #[test]
+    fn balance_change_below_threshold_is_not_significant() {
+        let cfg = ThresholdConfig {
+            min_volume_change_pct: 5.0,
+            ..Default::default()
+        };
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(50.0, false, &[50.0, 53.0]);
+        assert!(!new.is_significant_change_from(&old, &cfg));
+
+        let new = volume(50.0, false, &[50.0, 56.0]);
+        assert!(new.is_significant_change_from(&old, &cfg));
+    }
+
+    #[test]
+    fn volume_change_outside_epsilon_is_significant() {
+        let cfg = ThresholdConfig::default();
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(51.0, false, &[51.0, 51.0]);
+        assert!(new.is_significant_change_from(&old, &cfg));
+        assert_ne!(new, old);
+    }
+
+    #[test]
+    fn volumes_within_epsilon_are_equal() {
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(50.4, false, &[50.4, 49.6]);
+        assert_eq!(new, old);
+    }
+
+    #[test]
+    fn channel_only_change_is_significant() {
+        let cfg = ThresholdConfig::default();
+        let old = volume(50.0, false, &[50.0, 50.0]);
+        let new = volume(50.0, false, &[50.0, 45.0]);
+        assert!(new.is_significant_change_from(&old, &cfg));
+
+        let cfg = ThresholdConfig {
+            check_balance: false,
+            ..Default::default()
+        };
+        assert!(!new.is_significant_change_from(&old, &cfg));
+    }
+
+    #[test]
+    fn soft_volume_at_full_scale_is_ignored() {
+        let mut vol = volume(50.0, false, &[50.0, 50.0]);
+        vol.soft_volumes = vec![100.0, 100.0];
+        assert!(!vol.has_soft_volume());
+
+        vol.soft_volumes = vec![100.0, 99.0];
+        assert!(vol.has_soft_volume());
+    }
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, ops::Mul};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::CStr,
+    ops::Mul,
+};
 
 use crate::state;
 use anyhow::{Context, Result, anyhow};
@@ -6,27 +10,115 @@ use pipewire::{self as pw, proxy::ProxyT};
 use pw::{
     context::ContextRc,
     core::CoreRc,
-    registry::RegistryRc,
+    properties::PropertiesBox,
+    registry::{GlobalObject, RegistryRc},
+    spa::param::{
+        ParamType,
+        audio::{AudioFormat, AudioInfoRaw},
+        format::{MediaSubtype, MediaType},
+        format_utils,
+    },
     spa::pod::{Pod, Value, ValueArray, deserialize::PodDeserializer},
     spa::utils::dict::DictRef,
     thread_loop::ThreadLoopRc,
     types::ObjectType,
 };
+use tracing::debug;
 
 pub type PWContextRc = std::rc::Rc<PWContext>;
 
+/// Callback called when a registered object is removed.
+///
+/// Called while subscriptions are being updated, so it must not access [PWContext].
 type ObjectRemoveListener = dyn Fn(u32);
 
+/// Callback to store last reported object volume.
+///
+/// Returns difference from previously reported volume, if any.
+pub type VolumeTracker =
+    std::rc::Rc<dyn Fn(u32, &state::VolumeInfo) -> Option<state::VolumeInfoDelta>>;
+
+/// Callback to subscribe to params of a registered node or device.
+///
+/// Returns false if object isn't registered or has no params.
+pub type ParamsSubscriber = std::rc::Rc<dyn Fn(u32, &[ParamType]) -> bool>;
+
+/// Callback to set volume of a registered node.
+pub type VolumeSetter = std::rc::Rc<dyn Fn(u32, &state::VolumeInfo) -> Result<()>>;
+
+/// BoundObject is a registered PipeWire object proxy.
+///
+/// Nodes and devices are kept typed to call their methods after registration.
+enum BoundObject {
+    Node(pw::node::Node),
+    Device(pw::device::Device),
+    Other(Box<dyn ProxyT>),
+}
+
+impl BoundObject {
+    fn proxy(&self) -> &pw::proxy::Proxy {
+        match self {
+            Self::Node(node) => node.upcast_ref(),
+            Self::Device(dev) => dev.upcast_ref(),
+            Self::Other(obj) => obj.upcast_ref(),
+        }
+    }
+
+    /// Subscribes to object params.
+    ///
+    /// Returns false if object has no params.
+    fn subscribe_params(&self, ids: &[ParamType]) -> bool {
+        match self {
+            Self::Node(node) => node.subscribe_params(ids),
+            Self::Device(dev) => dev.subscribe_params(ids),
+            Self::Other(_) => return false,
+        }
+
+        true
+    }
+
+    /// Sets object param.
+    ///
+    /// Returns false if object has no params.
+    fn set_param(&self, id: ParamType, param: &Pod) -> bool {
+        match self {
+            Self::Node(node) => node.set_param(id, 0, param),
+            Self::Device(dev) => dev.set_param(id, 0, param),
+            Self::Other(_) => return false,
+        }
+
+        true
+    }
+}
+
 pub struct Subscriptions {
     /// listeners is key-value pair of registered event listeners per object.
     /// Keeps subscriptions alive until object exists.
     listeners: HashMap<u32, Vec<Box<dyn pw::proxy::Listener>>>,
 
     /// Registry of PipeWire objects to keep alive.
-    objects: HashMap<u32, Box<dyn pw::proxy::ProxyT>>,
+    objects: HashMap<u32, BoundObject>,
 
     /// Object destroy listeners.
     disposers: HashMap<u32, Vec<Box<ObjectRemoveListener>>>,
+
+    /// Types of registered objects.
+    object_types: HashMap<u32, ObjectType>,
+
+    /// Registry global IDs of registered objects.
+    globals: HashMap<u32, u32>,
+
+    /// Registry global IDs of subscribed links.
+    links: HashSet<u32>,
+
+    /// Registry global IDs of known clients.
+    clients: HashSet<u32>,
+
+    /// Last reported volume per object.
+    volumes: HashMap<u32, state::VolumeInfo>,
+
+    /// Audio registry objects by global ID. Used to bind objects on demand.
+    registry_objects: HashMap<u32, GlobalObject<PropertiesBox>>,
 }
 
 impl Subscriptions {
@@ -35,9 +127,40 @@ impl Subscriptions {
             listeners: HashMap::new(),
             objects: HashMap::new(),
             disposers: HashMap::new(),
+            object_types: HashMap::new(),
+            globals: HashMap::new(),
+            volumes: HashMap::new(),
+            links: HashSet::new(),
+            clients: HashSet::new(),
+            registry_objects: HashMap::new(),
         }
     }
 
+    fn track_volume(
+        &mut self,
+        oid: u32,
+        vol: &state::VolumeInfo,
+    ) -> Option<state::VolumeInfoDelta> {
+        self.volumes
+            .insert(oid, vol.clone())
+            .map(|prev| vol.delta_from(&prev))
+    }
+
+    fn set_volume(&self, oid: u32, vol: &state::VolumeInfo) -> Result<()> {
+        let obj = self
+            .objects
+            .get(&oid)
+            .ok_or_else(|| anyhow!("object {oid} is not registered"))?;
+
+        let data = vol.to_spa_props_pod()?;
+        let pod = Pod::from_bytes(&data).ok_or_else(|| anyhow!("invalid volume props pod"))?;
+        if !obj.set_param(ParamType::Props, pod) {
+            return Err(anyhow!("object {oid} has no params"));
+        }
+
+        Ok(())
+    }
+
     fn add_subscription(&mut self, oid: u32, listener: Box<dyn pw::proxy::Listener>) {
         self.listeners.entry(oid).or_default().push(listener);
     }
@@ -52,32 +175,143 @@ impl Subscriptions {
         }
     }
 
-    fn add_object(&mut self, obj: Box<dyn pw::proxy::ProxyT>) {
-        let oid = obj.upcast_ref().id();
+    fn add_object(&mut self, obj: BoundObject, type_: ObjectType) {
+        let oid = obj.proxy().id();
+        self.object_types.entry(oid).or_insert(type_);
         self.objects.entry(oid).or_insert(obj);
     }
 
+    /// Returns type of a registered object.
+    fn get_type(&self, oid: u32) -> Option<ObjectType> {
+        self.object_types.get(&oid).cloned()
+    }
+
     fn remove_object(&mut self, oid: u32) {
-        if let Some(subs) = self.disposers.get(&oid) {
-            for sub in subs {
-                sub(oid);
+        if let Some(disposers) = self.disposers.remove(&oid) {
+            for dispose in disposers {
+                dispose(oid);
             }
         }
 
-        self.disposers.remove(&oid);
         self.objects.remove(&oid);
+        self.object_types.remove(&oid);
         self.listeners.remove(&oid);
+        self.globals.retain(|_, id| *id != oid);
+        self.volumes.remove(&oid);
+    }
+
+    /// Calls a function for each registered object, sorted by ID.
+    ///
+    /// Function receives object ID, number of registered listeners and whether
+    /// object has removal listeners.
+    fn for_each_object<F: FnMut(u32, usize, bool)>(&self, mut f: F) {
+        let mut oids: Vec<_> = self.objects.keys().copied().collect();
+        oids.sort_unstable();
+
+        for oid in oids {
+            let listeners = self.listeners.get(&oid).map(Vec::len).unwrap_or(0);
+            let has_disposers = self.disposers.contains_key(&oid);
+            f(oid, listeners, has_disposers);
+        }
+    }
+
+    /// Returns type of a registered object, falling back to the type reported by its proxy.
+    fn object_type_or_proxy(&self, oid: u32) -> Option<ObjectType> {
+        self.get_type(oid)
+            .or_else(|| Some(self.objects.get(&oid)?.proxy().get_type().0))
+    }
+
+    /// Returns human-readable summary of registered objects and their listeners.
+    fn debug_dump(&self) -> String {
+        let mut out = format!(
+            "objects: {}, listeners: {}, globals: {}\n",
+            self.objects.len(),
+            self.listeners.values().map(Vec::len).sum::<usize>(),
+            self.globals.len(),
+        );
+
+        let mut by_type: BTreeMap<&str, usize> = BTreeMap::new();
+        for obj_type in self.object_types.values() {
+            *by_type.entry(obj_type.to_str()).or_default() += 1;
+        }
+
+        for (obj_type, count) in by_type {
+            out.push_str(&format!("{obj_type}: {count}\n"));
+        }
+
+        self.for_each_object(|oid, listeners, has_disposers| {
+            let obj_type = self
+                .object_type_or_proxy(oid)
+                .map(|t| t.to_str().to_string())
+                .unwrap_or_default();
+            let disposers = if has_disposers { ", removal hooks" } else { "" };
+            out.push_str(&format!(
+                "  #{oid} {obj_type}: {listeners} listener(s){disposers}\n"
+            ));
+        });
+
+        out
     }
 
     fn clear(&mut self) {
         self.disposers.clear();
         self.listeners.clear();
+        self.globals.clear();
+        self.volumes.clear();
+        self.links.clear();
+        self.clients.clear();
+        self.registry_objects.clear();
 
         // TODO: investigate why this cause 'impl_ext_end_proxy called from wrong context, check thread and locking: Operation not permitted'.
         // self.objects.clear();
     }
 }
 
+// Proxies and listeners are opaque, so only IDs and counts are printed.
+impl std::fmt::Debug for Subscriptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut objects: Vec<_> = self.objects.keys().copied().collect();
+        objects.sort_unstable();
+
+        let listeners: BTreeMap<_, _> = self
+            .listeners
+            .iter()
+            .map(|(oid, l)| (*oid, l.len()))
+            .collect();
+
+        f.debug_struct("Subscriptions")
+            .field("objects", &objects)
+            .field("listeners", &listeners)
+            .field("globals", &self.globals.iter().collect::<BTreeMap<_, _>>())
+            .field("links", &self.links.len())
+            .field("clients", &self.clients.len())
+            .field("registry_objects", &self.registry_objects.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// LookupError is returned when PipeWire object can't be found by name.
+#[derive(Debug)]
+pub enum LookupError {
+    NotFound(String),
+
+    /// Several objects have the same name. Contains labels of all matches.
+    Ambiguous(Vec<String>),
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookupError::NotFound(name) => write!(f, "object {name:?} not found"),
+            LookupError::Ambiguous(labels) => {
+                write!(f, "name matches multiple objects: {}", labels.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
 /// PWContext holds all core PipeWire objects.
 pub struct PWContext {
     pub context: ContextRc,
@@ -88,9 +322,18 @@ pub struct PWContext {
     subs: std::rc::Rc<std::cell::RefCell<Subscriptions>>,
 }
 
+impl std::fmt::Debug for PWContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PWContext")
+            .field("subs", &*self.subs.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
 impl PWContext {
-    pub fn new() -> Result<Self> {
-        let tloop = new_thread_loop().context("can't create thread loop")?;
+    /// Creates context with a thread loop named `thread_name`.
+    pub fn new(thread_name: Option<&CStr>) -> Result<Self> {
+        let tloop = new_named_thread_loop(thread_name).context("can't create thread loop")?;
         let ctx = ContextRc::new(&tloop, None).context("can't create pw context")?;
         let core = ctx
             .connect_rc(None)
@@ -105,8 +348,8 @@ impl PWContext {
         })
     }
 
-    pub fn new_shared() -> Result<PWContextRc> {
-        let ctx = Self::new()?;
+    pub fn new_shared(thread_name: Option<&CStr>) -> Result<PWContextRc> {
+        let ctx = Self::new(thread_name)?;
         Ok(std::rc::Rc::new(ctx))
     }
 
@@ -120,8 +363,7 @@ impl PWContext {
         let listener = Box::new(builder(oid, node.add_listener_local()).register());
         self.subs.borrow_mut().add_subscription(oid, listener);
 
-        let proxy: Box<dyn ProxyT> = Box::new(node);
-        self.register_object(oid, proxy);
+        self.register_object(oid, BoundObject::Node(node), ObjectType::Node);
         oid
     }
 
@@ -138,20 +380,194 @@ impl PWContext {
         let listener = Box::new(builder(oid, dev.add_listener_local()).register());
         self.subs.borrow_mut().add_subscription(oid, listener);
 
-        let proxy: Box<dyn ProxyT> = Box::new(dev);
-        self.register_object(oid, proxy);
+        self.register_object(oid, BoundObject::Device(dev), ObjectType::Device);
         oid
     }
 
-    pub fn removed_listener(&self, oid: u32, handler: Box<dyn Fn(u32)>) -> Result<()> {
+    /// Adds a metadata property change listener.
+    ///
+    /// Callback receives subject, key and value of a changed property.
+    /// Property removals are skipped. If `key_filter` is set, only properties with
+    /// matching key are reported.
+    ///
+    /// Returns object ID.
+    pub fn register_metadata_listener<F>(
+        &self,
+        obj: pw::metadata::Metadata,
+        key_filter: Option<&str>,
+        callback: F,
+    ) -> u32
+    where
+        F: Fn(u32, &str, &str) + 'static,
+    {
+        let oid = obj.upcast_ref().id();
+        let key_filter = key_filter.map(str::to_string);
+        let listener = obj
+            .add_listener_local()
+            .property(move |subject, key, _type, value| {
+                if let (Some(key), Some(value)) = (key, value)
+                    && key_filter.as_deref().is_none_or(|f| f == key)
+                {
+                    callback(subject, key, value);
+                }
+                0
+            })
+            .register();
+        self.subs
+            .borrow_mut()
+            .add_subscription(oid, Box::new(listener));
+
+        self.register_object(oid, BoundObject::Other(Box::new(obj)), ObjectType::Metadata);
+        oid
+    }
+
+    /// Adds a new link event listener.
+    /// Returns object ID.
+    pub fn link_listener_local<F>(&self, link: pw::link::Link, builder: F) -> u32
+    where
+        F: Fn(u32, pw::link::LinkListenerLocalBuilder) -> pw::link::LinkListenerLocalBuilder,
+    {
+        let oid = link.upcast_ref().id();
+        let listener = Box::new(builder(oid, link.add_listener_local()).register());
+        self.subs.borrow_mut().add_subscription(oid, listener);
+
+        self.register_object(oid, BoundObject::Other(Box::new(link)), ObjectType::Link);
+        oid
+    }
+
+    /// Marks registry global ID as a subscribed link.
+    pub fn track_link(&self, global_id: u32) {
+        self.subs.borrow_mut().links.insert(global_id);
+    }
+
+    /// Returns whether link with registry global ID is subscribed.
+    pub fn is_link_tracked(&self, global_id: u32) -> bool {
+        self.subs.borrow().links.contains(&global_id)
+    }
+
+    /// Forgets a link removed from registry.
+    ///
+    /// Returns false if ID doesn't belong to a subscribed link.
+    pub fn forget_link(&self, global_id: u32) -> bool {
+        self.subs.borrow_mut().links.remove(&global_id)
+    }
+
+    /// Marks registry global ID as a known client.
+    ///
+    /// Returns false if client is already known.
+    pub fn track_client(&self, global_id: u32) -> bool {
+        self.subs.borrow_mut().clients.insert(global_id)
+    }
+
+    /// Forgets a client removed from registry.
+    ///
+    /// Returns false if ID doesn't belong to a known client.
+    pub fn forget_client(&self, global_id: u32) -> bool {
+        self.subs.borrow_mut().clients.remove(&global_id)
+    }
+
+    /// Associates registry global ID with a registered object ID.
+    pub fn register_global(&self, global_id: u32, oid: u32) {
+        self.subs.borrow_mut().globals.insert(global_id, oid);
+    }
+
+    /// Returns registered object ID for a registry global ID.
+    pub fn find_object(&self, global_id: u32) -> Option<u32> {
+        self.subs.borrow().globals.get(&global_id).copied()
+    }
+
+    /// Remembers registry object to bind it later by name.
+    pub fn track_global(&self, o: &PWGlobalObject) {
+        self.subs
+            .borrow_mut()
+            .registry_objects
+            .insert(o.id, o.to_owned());
+    }
+
+    /// Forgets registry object removed from registry.
+    pub fn forget_global(&self, global_id: u32) {
+        self.subs.borrow_mut().registry_objects.remove(&global_id);
+    }
+
+    /// Finds audio node by `node.name` and binds it.
+    #[allow(dead_code)]
+    pub fn bind_node_by_name(&self, name: &str) -> Result<pw::node::Node> {
+        self.bind_by_name(ObjectType::Node, *pipewire::keys::NODE_NAME, name)
+    }
+
+    /// Finds audio device by `device.name` and binds it.
+    #[allow(dead_code)]
+    pub fn bind_device_by_name(&self, name: &str) -> Result<pw::device::Device> {
+        self.bind_by_name(ObjectType::Device, *pipewire::keys::DEVICE_NAME, name)
+    }
+
+    fn bind_by_name<T: ProxyT>(&self, type_: ObjectType, name_key: &str, name: &str) -> Result<T> {
+        let subs = self.subs.borrow();
+        let matches: Vec<_> = subs
+            .registry_objects
+            .values()
+            .filter(|o| {
+                o.type_ == type_ && o.props.as_ref().and_then(|p| p.get(name_key)) == Some(name)
+            })
+            .collect();
+
+        let obj = match matches.as_slice() {
+            [] => return Err(LookupError::NotFound(name.to_string()).into()),
+            [obj] => *obj,
+            _ => {
+                let labels = matches.iter().map(|o| format_object_label(*o)).collect();
+                return Err(LookupError::Ambiguous(labels).into());
+            }
+        };
+
+        self.registry
+            .bind(obj)
+            .with_context(|| format!("failed to bind {}", format_object_label(obj)))
+    }
+
+    /// Sets volume of a registered node.
+    ///
+    /// Devices are not supported as device volume is set via `Route` param.
+    ///
+    /// New volume is reported back to object's param listener as a regular change event.
+    #[allow(dead_code)]
+    pub fn set_volume(&self, oid: u32, vol: &state::VolumeInfo) -> Result<()> {
+        self.subs.borrow().set_volume(oid, vol)
+    }
+
+    /// Returns a callback to compute volume change relative to previously reported volume.
+    pub fn volume_tracker(&self) -> VolumeTracker {
+        let subs = self.subs.clone();
+        std::rc::Rc::new(move |oid, vol| subs.borrow_mut().track_volume(oid, vol))
+    }
+
+    /// Returns a callback to set volume of a registered object, see [`Self::set_volume`].
+    pub fn volume_setter(&self) -> VolumeSetter {
+        let subs = self.subs.clone();
+        std::rc::Rc::new(move |oid, vol| subs.borrow().set_volume(oid, vol))
+    }
+
+    /// Returns a callback to subscribe to params of a registered object later.
+    pub fn params_subscriber(&self) -> ParamsSubscriber {
+        let subs = self.subs.clone();
+        std::rc::Rc::new(move |oid, ids| {
+            subs.borrow()
+                .objects
+                .get(&oid)
+                .is_some_and(|obj| obj.subscribe_params(ids))
+        })
+    }
+
+    /// Adds a listener called when a registered object is removed.
+    pub fn removed_listener(&self, oid: u32, handler: Box<ObjectRemoveListener>) -> Result<()> {
         self.subs.borrow_mut().on_object_remove(oid, handler)
     }
 
-    fn register_object(&self, oid: u32, proxy: Box<dyn ProxyT>) {
+    fn register_object(&self, oid: u32, obj: BoundObject, type_: ObjectType) {
         // Register object in keepalive list and listener to remove it.
         let subs = self.subs.clone();
-        let removed_listener = proxy
-            .upcast_ref()
+        let removed_listener = obj
+            .proxy()
             .add_listener_local()
             .removed(move || {
                 subs.borrow_mut().remove_object(oid);
@@ -161,7 +577,25 @@ impl PWContext {
         self.subs
             .borrow_mut()
             .add_subscription(oid, Box::new(removed_listener));
-        self.subs.borrow_mut().add_object(proxy);
+        self.subs.borrow_mut().add_object(obj, type_);
+    }
+
+    /// Calls a function for each registered object with its listener count and whether
+    /// it has removal listeners.
+    #[allow(dead_code)]
+    pub fn iter_subscriptions<F: FnMut(u32, usize, bool)>(&self, f: F) {
+        self.subs.borrow().for_each_object(f);
+    }
+
+    /// Returns type of a registered object.
+    #[allow(dead_code)]
+    pub fn object_type(&self, oid: u32) -> Option<ObjectType> {
+        self.subs.borrow().get_type(oid)
+    }
+
+    /// Returns summary of registered objects and listeners for debugging.
+    pub fn dump_subscriptions(&self) -> String {
+        self.subs.borrow().debug_dump()
     }
 
     /// Starts event loop.
@@ -173,42 +607,73 @@ impl PWContext {
         // Run until callback completes.
         cb();
 
+        debug!(
+            "subscriptions before cleanup:\n{}",
+            self.dump_subscriptions()
+        );
         self.subs.borrow_mut().clear();
         self.thread_loop.stop();
     }
 }
 
+#[allow(dead_code)]
 pub fn new_thread_loop() -> Result<ThreadLoopRc, pipewire::Error> {
-    unsafe { ThreadLoopRc::new(None, None) }
+    new_named_thread_loop(None)
 }
 
-pub fn is_audio_node(props: &Option<&DictRef>) -> bool {
+/// Creates a thread loop with specified thread name.
+///
+/// Name is displayed in process monitors like `top`.
+pub fn new_named_thread_loop(name: Option<&CStr>) -> Result<ThreadLoopRc, pipewire::Error> {
+    unsafe { ThreadLoopRc::new_cstr(name, None) }
+}
+
+/// Returns device kind for audio nodes or `None` if node isn't an audio node.
+pub fn classify_audio_node(props: &Option<&DictRef>) -> Option<state::DeviceKind> {
     props
         .and_then(|p| p.get(*pipewire::keys::MEDIA_CLASS))
-        .map(|media_class| match media_class {
-            "Audio/Sink" | "Audio/Source" | "Audio/Duplex" => true,
-            "Audio/Sink/Monitor" => true, // Monitor sources for recording
-            _ => false,
+        .and_then(|media_class| match media_class {
+            "Audio/Sink" | "Audio/Source" | "Audio/Duplex" => Some(media_class.into()),
+            "Audio/Sink/Monitor" => Some(media_class.into()), // Monitor sources for recording
+            _ => None,
         })
-        .unwrap_or(false)
 }
 
-pub fn is_audio_device(props: &Option<&DictRef>) -> bool {
+/// Returns device kind for audio devices or `None` if device isn't an audio device.
+pub fn classify_audio_device(props: &Option<&DictRef>) -> Option<state::DeviceKind> {
+    let props = (*props)?;
+    let device_api = props.get(*pipewire::keys::DEVICE_API)?;
+
+    // ALSA, Bluetooth, JACK and PulseAudio devices.
+    if let state::DeviceApiClass::Unknown(_) = state::DeviceApiClass::from(device_api) {
+        return None;
+    }
+
+    let kind = props
+        .get(*pipewire::keys::MEDIA_CLASS)
+        .map(state::DeviceKind::from)
+        .unwrap_or(state::DeviceKind::Device);
+    Some(kind)
+}
+
+pub fn is_audio_node(props: &Option<&DictRef>) -> bool {
+    classify_audio_node(props).is_some()
+}
+
+/// Returns API class of an audio device or `None` if device isn't an audio device.
+pub fn is_audio_device(props: &Option<&DictRef>) -> Option<state::DeviceApiClass> {
+    classify_audio_device(props)?;
     props
         .and_then(|p| p.get(*pipewire::keys::DEVICE_API))
-        .map(|device_api| match device_api {
-            "alsa" => true,   // ALSA audio devices
-            "bluez5" => true, // Bluetooth audio devices
-            "jack" => true,   // JACK audio devices
-            "pulse" => true,  // PulseAudio devices
-            _ => false,
-        })
-        .unwrap_or(false)
+        .map(state::DeviceApiClass::from)
 }
 
+/// Converts cubic volume value to linear percent.
+///
+/// Values above 100% used for amplification are kept, they are limited later
+/// by [state::VolumeInfo::normalize_channel_volumes].
 fn normalize_volume_value(v: f32) -> f32 {
-    // Convert value to linear and then to percent.
-    v.clamp(0.0, 1.0).powf(1.0 / 3.0).mul(100.0).round()
+    v.max(0.0).powf(1.0 / 3.0).mul(100.0).round()
 }
 
 fn normalize_channel_volumes(v: Vec<f32>) -> Vec<f32> {
@@ -228,7 +693,17 @@ pub fn volume_from_pod(param: &Pod) -> Option<state::VolumeInfo> {
         match key {
             pipewire::spa::sys::SPA_PROP_volume => {
                 found = true;
-                vol_info.volume = value_pod.get_float().map(normalize_volume_value).ok();
+                vol_info.volume = match value_pod.get_float() {
+                    Ok(v) => Some(normalize_volume_value(v)),
+                    // Some drivers report volume as an array.
+                    Err(_) => match PodDeserializer::deserialize_any_from(value_pod.as_bytes()) {
+                        Ok((_, Value::ValueArray(ValueArray::Float(volumes)))) => {
+                            debug!(?volumes, "volume prop is an array, using first element");
+                            volumes.first().copied().map(normalize_volume_value)
+                        }
+                        _ => None,
+                    },
+                };
             }
             pipewire::spa::sys::SPA_PROP_mute => {
                 found = true;
@@ -242,6 +717,24 @@ pub fn volume_from_pod(param: &Pod) -> Option<state::VolumeInfo> {
                     vol_info.channel_volumes = normalize_channel_volumes(volumes);
                 }
             }
+            pipewire::spa::sys::SPA_PROP_softVolumes => {
+                if let Ok((_, Value::ValueArray(ValueArray::Float(volumes)))) =
+                    PodDeserializer::deserialize_any_from(value_pod.as_bytes())
+                {
+                    vol_info.soft_volumes =
+                        volumes.into_iter().map(normalize_volume_value).collect();
+                }
+            }
+            pipewire::spa::sys::SPA_PROP_channelMap => {
+                if let Ok((_, Value::ValueArray(ValueArray::Id(positions)))) =
+                    PodDeserializer::deserialize_any_from(value_pod.as_bytes())
+                {
+                    vol_info.channel_map = positions
+                        .into_iter()
+                        .map(|pos| state::SpaChannelPosition(pos.0))
+                        .collect();
+                }
+            }
             _ => {
                 // debug!(?key, "Skip prop key");
             }
@@ -267,66 +760,108 @@ pub fn volume_from_pod(param: &Pod) -> Option<state::VolumeInfo> {
     }
 }
 
+/// Parses negotiated audio format from a `Format` param.
+///
+/// Returns `None` for non-audio or encoded formats.
+pub fn audio_format_from_pod(param: &Pod) -> Option<state::AudioFormat> {
+    let (media_type, media_subtype) = format_utils::parse_format(param).ok()?;
+    if media_type != MediaType::Audio || media_subtype != MediaSubtype::Raw {
+        return None;
+    }
+
+    let mut info = AudioInfoRaw::new();
+    info.parse(param).ok()?;
+
+    Some(state::AudioFormat {
+        sample_rate: Some(info.rate()).filter(|rate| *rate > 0),
+        sample_format: Some(info.format()).filter(|f| *f != AudioFormat::Unknown),
+    })
+}
+
 pub type PWGlobalObject<'a> =
     pipewire::registry::GlobalObject<&'a pipewire::spa::utils::dict::DictRef>;
 
-pub fn parse_object(o: &PWGlobalObject) -> Option<state::Entry> {
-    let props = match &o.props {
-        Some(props) => props,
-        None => {
-            eprintln!("pw: ignore node without props: {}", o.id);
-            return None;
-        }
-    };
+/// Function to convert raw PipeWire object name into a display name.
+pub type NameTransform = dyn Fn(&str) -> String + Send + Sync;
 
-    let dev = match o.type_ {
-        ObjectType::Node if is_audio_node(&o.props) => state::Entry {
-            id: o.id,
-            volume: None,
-            is_node: true,
-            name: props.get("node.name").map(|v| v.to_string()),
-            device_id: props.get("device.id").and_then(|v| v.parse::<u32>().ok()),
-            label: props
-                .get("node.nick")
-                .or_else(|| props.get("node.description"))
-                .map(|v| v.to_string()),
-            description: props.get("node.description").map(|v| v.to_string()),
-            kind: props
-                .get("media.class")
-                .map(|v| v.into())
-                .unwrap_or(state::DeviceKind::Unknown),
-        },
-        ObjectType::Device if is_audio_device(&o.props) => state::Entry {
-            id: o.id,
-            volume: None,
-            is_node: false,
-            name: props.get("device.name").map(|v| v.to_string()),
-            device_id: props.get("device.id").and_then(|v| v.parse::<u32>().ok()),
-            label: props
-                .get("device.description")
-                .or_else(|| props.get("device.name"))
-                .map(|v| v.to_string()),
-            description: props.get("device.description").map(|v| v.to_string()),
-            kind: props
-                .get("media.class")
-                .map(|v| v.into())
-                .unwrap_or(state::DeviceKind::Unknown),
-        },
-        _ => {
-            // eprintln!("pw: ignore unsupported object type: {}", o.type_);
-            return None;
-        }
-    };
+/// Builds entry from PipeWire global object.
+///
+/// If `name_transform` is set, it's applied to the raw object name to produce entry label.
+#[allow(dead_code)]
+#[deprecated(note = "use Entry::from_global_object")]
+pub fn parse_object(
+    o: &PWGlobalObject,
+    name_transform: Option<&NameTransform>,
+) -> Option<state::Entry> {
+    let mut entry = state::Entry::from_global_object(o)?;
+    if let Some(transform) = name_transform {
+        entry.apply_name_transform(transform);
+    }
 
-    Some(dev)
+    Some(entry)
+}
+
+/// Builds link entry from PipeWire global object.
+///
+/// Link state isn't available in registry and is set to `Unknown`.
+pub fn parse_link(o: &PWGlobalObject) -> Option<state::LinkEntry> {
+    if o.type_ != ObjectType::Link {
+        return None;
+    }
+
+    let props = o.props?;
+    let get_id = |key: &str| props.get(key).and_then(|v| v.parse::<u32>().ok());
+    Some(state::LinkEntry {
+        id: o.id,
+        output_node: get_id("link.output.node")?,
+        output_port: get_id("link.output.port")?,
+        input_node: get_id("link.input.node")?,
+        input_port: get_id("link.input.port")?,
+        state: state::LinkState::Unknown,
+    })
+}
+
+/// Builds client entry from PipeWire global object.
+pub fn parse_client(o: &PWGlobalObject) -> Option<state::ClientEntry> {
+    if o.type_ != ObjectType::Client {
+        return None;
+    }
+
+    let props = o.props?;
+    Some(state::ClientEntry {
+        id: o.id,
+        app_name: props.get(*pipewire::keys::APP_NAME).map(|v| v.to_string()),
+        pid: props
+            .get(*pipewire::keys::APP_PROCESS_ID)
+            .and_then(|v| v.parse::<u32>().ok()),
+    })
+}
+
+/// Returns device name from a `default` metadata value, e.g. `{"name":"alsa_output.pci"}`.
+pub fn parse_metadata_device_name(value: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(value).ok()?;
+    value.get("name")?.as_str().map(|v| v.to_string())
+}
+
+/// Converts PipeWire link state.
+pub fn link_state(st: pw::link::LinkState) -> state::LinkState {
+    match st {
+        pw::link::LinkState::Error(err) => state::LinkState::Error(err.to_string()),
+        pw::link::LinkState::Unlinked => state::LinkState::Unlinked,
+        pw::link::LinkState::Init => state::LinkState::Init,
+        pw::link::LinkState::Negotiating => state::LinkState::Negotiating,
+        pw::link::LinkState::Allocating => state::LinkState::Allocating,
+        pw::link::LinkState::Paused => state::LinkState::Paused,
+        pw::link::LinkState::Active => state::LinkState::Active,
+    }
 }
 
 /// Returns PipeWire object friendly name.
 ///
 /// Usually used for logging.
-pub fn format_object_label(o: &PWGlobalObject) -> String {
+pub fn format_object_label<P: AsRef<DictRef>>(o: &GlobalObject<P>) -> String {
     let props = match &o.props {
-        Some(props) => props,
+        Some(props) => props.as_ref(),
         None => return format!("<{}>", o.id),
     };
 
@@ -347,3 +882,44 @@ pub fn format_object_label(o: &PWGlobalObject) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(vol: &state::VolumeInfo) -> state::VolumeInfo {
+        let data = vol.to_spa_props_pod().expect("volume is serialized");
+        let pod = Pod::from_bytes(&data).expect("pod is valid");
+        volume_from_pod(pod).expect("volume is parsed")
+    }
+
+    #[test]
+    fn channel_volumes_survive_props_pod_round_trip() {
+        let vol = state::VolumeInfo {
+            volume: Some(50.0),
+            mute: Some(true),
+            channel_volumes: vec![50.0, 40.0],
+            ..Default::default()
+        };
+
+        let parsed = round_trip(&vol);
+        assert_eq!(parsed.mute, Some(true));
+        assert_eq!(parsed.channel_volumes, vec![50.0, 40.0]);
+
+        // Master volume isn't written, so it's taken from the first channel.
+        assert_eq!(parsed.volume, Some(50.0));
+    }
+
+    #[test]
+    fn master_volume_is_written_without_channel_volumes() {
+        let vol = state::VolumeInfo {
+            volume: Some(30.0),
+            ..Default::default()
+        };
+
+        let parsed = round_trip(&vol);
+        assert_eq!(parsed.volume, Some(30.0));
+        assert_eq!(parsed.mute, None);
+        assert!(parsed.channel_volumes.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Last known volumes of nodes, persisted across PipeWire restarts.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::state::VolumeInfo;
+
+const VOLUMES_FILE_NAME: &str = "volumes.json";
+
+/// VolumeStore keeps the last known volume per entry name in a JSON file.
+///
+/// Entries are identified by `node.name`. Object IDs and serials are assigned again
+/// after PipeWire restart, so neither of them identifies the same node across restarts.
+#[derive(Debug, Clone)]
+pub struct VolumeStore {
+    path: PathBuf,
+    volumes: HashMap<String, VolumeInfo>,
+}
+
+impl VolumeStore {
+    /// Returns default store location - `$XDG_DATA_HOME/reactord/volumes.json`.
+    ///
+    /// Falls back to `~/.local/share` if `XDG_DATA_HOME` is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share"))
+            })?;
+
+        Some(
+            data_dir
+                .join(env!("CARGO_PKG_NAME"))
+                .join(VOLUMES_FILE_NAME),
+        )
+    }
+
+    /// Opens a store at specified path.
+    ///
+    /// Missing file is treated as an empty store. Malformed items are skipped.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path,
+                    volumes: HashMap::new(),
+                });
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        let doc: Value = serde_json::from_str(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        let items = doc
+            .as_object()
+            .ok_or_else(|| anyhow!("{} is not a JSON object", path.display()))?;
+
+        let volumes = items
+            .iter()
+            .filter_map(|(name, v)| match volume_from_json(v) {
+                Some(vol) => Some((name.clone(), vol)),
+                None => {
+                    warn!(name, path = %path.display(), "skip malformed volume record");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Self { path, volumes })
+    }
+
+    /// Returns the last saved volume of an entry.
+    pub fn load(&self, name: &str) -> Option<VolumeInfo> {
+        self.volumes.get(name).cloned()
+    }
+
+    /// Saves entry volume and writes the store to disk.
+    ///
+    /// File isn't touched if volume didn't change.
+    pub fn save(&mut self, name: &str, vol: &VolumeInfo) -> Result<()> {
+        if self.volumes.get(name) == Some(vol) {
+            return Ok(());
+        }
+
+        self.volumes.insert(name.to_string(), vol.clone());
+        self.flush()
+    }
+
+    fn flush(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+
+        let items: serde_json::Map<String, Value> = self
+            .volumes
+            .iter()
+            .map(|(name, vol)| (name.clone(), volume_to_json(vol)))
+            .collect();
+        let data = serde_json::to_string_pretty(&Value::Object(items))?;
+
+        // Write to a temporary file first to keep the store intact on failure.
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, data)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("failed to replace {}", self.path.display()))
+    }
+}
+
+/// Converts volume into a JSON record.
+///
+/// Channel map isn't stored as it's not used to set volume.
+fn volume_to_json(vol: &VolumeInfo) -> Value {
+    json!({
+        "volume": vol.volume,
+        "mute": vol.mute,
+        "channel_volumes": vol.channel_volumes,
+    })
+}
+
+fn volume_from_json(v: &Value) -> Option<VolumeInfo> {
+    let obj = v.as_object()?;
+    let channel_volumes = match obj.get("channel_volumes") {
+        Some(items) => items
+            .as_array()?
+            .iter()
+            .map(|v| v.as_f64().map(|v| v as f32))
+            .collect::<Option<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
+    Some(VolumeInfo {
+        volume: obj.get("volume").and_then(Value::as_f64).map(|v| v as f32),
+        mute: obj.get("mute").and_then(Value::as_bool),
+        channel_volumes,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "{}-{}-{name}.json",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn saved_volume_is_loaded_by_name() {
+        let path = temp_path("by-name");
+        let vol = VolumeInfo {
+            volume: Some(40.0),
+            mute: Some(false),
+            channel_volumes: vec![40.0, 40.0],
+            ..Default::default()
+        };
+
+        let mut store = VolumeStore::open(path.clone()).unwrap();
+        store.save("alsa_output.pci", &vol).unwrap();
+
+        let store = VolumeStore::open(path.clone()).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(store.load("alsa_output.pci"), Some(vol));
+        assert_eq!(store.load("alsa_output.hdmi"), None);
+    }
+
+    #[test]
+    fn malformed_records_are_skipped() {
+        let path = temp_path("malformed");
+        fs::write(
+            &path,
+            r#"{"alsa_output.pci": {"volume": 50.0}, "alsa_output.hdmi": {"channel_volumes": "30"}}"#,
+        )
+        .unwrap();
+
+        let store = VolumeStore::open(path.clone()).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(store.volumes.len(), 1);
+        assert_eq!(
+            store.load("alsa_output.pci").and_then(|v| v.volume),
+            Some(50.0)
+        );
+    }
+}